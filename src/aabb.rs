@@ -0,0 +1,93 @@
+use crate::ray::Ray;
+use crate::vec3::{Point3, Scalar, Vec3};
+
+// Axis aligned bounding box
+#[derive(Copy, Clone)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Aabb::empty()
+    }
+}
+
+impl Aabb {
+    pub fn new(a: Point3, b: Point3) -> Aabb {
+        Aabb {
+            min: Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+            max: Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())),
+        }
+    }
+
+    // contains nothing, neutral element of surrounding()
+    pub const fn empty() -> Aabb {
+        Aabb {
+            min: Point3::uni(Scalar::INFINITY),
+            max: Point3::uni(Scalar::NEG_INFINITY),
+        }
+    }
+
+    pub fn surrounding(a: &Aabb, b: &Aabb) -> Aabb {
+        Aabb {
+            min: Point3::new(a.min.x().min(b.min.x()), a.min.y().min(b.min.y()), a.min.z().min(b.min.z())),
+            max: Point3::new(a.max.x().max(b.max.x()), a.max.y().max(b.max.y()), a.max.z().max(b.max.z())),
+        }
+    }
+
+    // avoid zero thickness boxes, for axis aligned quads
+    pub fn padded(&self) -> Aabb {
+        const DELTA: Scalar = 0.0001;
+        let mut padded = *self;
+        for axis in 0..3 {
+            if padded.max.e[axis] - padded.min.e[axis] < DELTA {
+                padded.min.e[axis] -= DELTA / 2.0;
+                padded.max.e[axis] += DELTA / 2.0;
+            }
+        }
+        padded
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let e = self.extent();
+        if e.x() > e.y() && e.x() > e.z() {
+            0
+        } else if e.y() > e.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    // slab test
+    pub fn hit(&self, ray: &Ray, t_min: Scalar, t_max: Scalar) -> bool {
+        let origin = ray.origin();
+        let direction = ray.direction();
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for axis in 0..3 {
+            let inv_d = 1.0 / direction.e[axis];
+            let mut t0 = (self.min.e[axis] - origin.e[axis]) * inv_d;
+            let mut t1 = (self.max.e[axis] - origin.e[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,193 @@
+use crate::color::{self, Color};
+use crate::framebuffer::FrameBuffer;
+use crate::tile::Tile;
+use crate::vec3::Scalar;
+
+// Running sums of the samples of one pixel
+#[derive(Copy, Clone, Default)]
+pub struct PixelSum {
+    pub sum: Color,
+    // of the luminance, for the variance
+    pub sum_squares: Scalar,
+    pub samples: u32,
+}
+
+impl PixelSum {
+    pub fn add(&mut self, c: Color) {
+        self.sum += c;
+        self.sum_squares += color::luminance(c) * color::luminance(c);
+        self.samples += 1;
+    }
+
+    pub fn mean(&self) -> Color {
+        if self.samples == 0 { color::black() } else { self.sum / self.samples as Scalar }
+    }
+
+    // variance of the mean luminance, None until there are enough samples
+    pub fn variance_of_mean(&self) -> Option<Scalar> {
+        if self.samples < 2 {
+            return None;
+        }
+        let n = self.samples as Scalar;
+        let mean = color::luminance(self.sum) / n;
+        let variance = ((self.sum_squares - n * mean * mean) / (n - 1.0)).max(0.0);
+        Some(variance / n)
+    }
+
+    // standard error of the mean luminance relative to it
+    pub fn relative_error(&self) -> Option<Scalar> {
+        let mean = color::luminance(self.mean());
+        // the offset keeps the almost black pixels from never converging
+        self.variance_of_mean().map(|v| v.sqrt() / (mean + 1e-3))
+    }
+}
+
+// Samples weighted by the reconstruction filter, from this pixel and its neighbours
+#[derive(Copy, Clone, Default)]
+pub struct Splat {
+    pub sum: Color,
+    pub weight: Scalar,
+}
+
+impl Splat {
+    pub fn add(&mut self, c: Color, weight: Scalar) {
+        self.sum += weight * c;
+        self.weight += weight;
+    }
+}
+
+// Samples of all the passes of a progressive render.
+// The error estimates only use the samples taken in each pixel, the image is made of the splats.
+#[derive(Clone)]
+pub struct Accumulator {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<PixelSum>,
+    splats: Vec<Splat>,
+}
+
+impl Accumulator {
+    pub fn new(width: u32, height: u32) -> Accumulator {
+        Accumulator {
+            width,
+            height,
+            pixels: vec![PixelSum::default(); (width * height) as usize],
+            splats: vec![Splat::default(); (width * height) as usize],
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> &PixelSum {
+        &self.pixels[(y * self.width + x) as usize]
+    }
+
+    // of each pixel so far, row by row
+    pub fn sample_counts(&self) -> Vec<u32> {
+        self.pixels.iter().map(|p| p.samples).collect()
+    }
+
+    // pixels in the order of the tile rows
+    pub fn add_tile(&mut self, tile: &Tile, pixels: &[PixelSum]) {
+        for (i, p) in pixels.iter().enumerate() {
+            let i = i as u32;
+            let x = tile.x0 + i % tile.width();
+            let y = tile.y0 + i / tile.width();
+            let target = &mut self.pixels[(y * self.width + x) as usize];
+            target.sum += p.sum;
+            target.sum_squares += p.sum_squares;
+            target.samples += p.samples;
+        }
+    }
+
+    // splats in the order of the rows of area, which can overlap other tiles
+    pub fn add_splats(&mut self, area: &Tile, splats: &[Splat]) {
+        for (i, s) in splats.iter().enumerate() {
+            let i = i as u32;
+            let x = area.x0 + i % area.width();
+            let y = area.y0 + i / area.width();
+            let target = &mut self.splats[(y * self.width + x) as usize];
+            target.sum += s.sum;
+            target.weight += s.weight;
+        }
+    }
+
+    pub fn converged(&self, threshold: Scalar) -> bool {
+        self.pixels.iter().all(|p| p.relative_error().is_some_and(|e| e <= threshold))
+    }
+
+    // Samples of the next pass, at most pass_budget in total, spread proportionally to the error.
+    // The pixels under the threshold are left alone, a single pixel cannot take more than a tenth of the pass.
+    pub fn adaptive_counts(&self, pass_budget: u64, remaining: u64, threshold: Option<Scalar>) -> Vec<u32> {
+        let errors: Vec<Scalar> = self.pixels.iter()
+            .map(|p| match p.relative_error() {
+                Some(e) if threshold.is_some_and(|t| e <= t) => 0.0,
+                Some(e) => e,
+                None => Scalar::INFINITY,
+            })
+            .collect();
+        let max_finite = errors.iter().cloned().filter(|e| e.is_finite()).fold(0.0, Scalar::max);
+        let errors: Vec<Scalar> = errors.iter()
+            .map(|&e| if e.is_finite() { e } else { max_finite.max(1.0) })
+            .collect();
+        let total: Scalar = errors.iter().sum();
+        if total <= 0.0 {
+            return vec![0; self.pixels.len()];
+        }
+
+        let budget = pass_budget.min(remaining) as Scalar;
+        let cap = (budget / 10.0).ceil();
+        let mut counts: Vec<u32> = errors.iter()
+            .map(|e| (budget * e / total).round().min(cap) as u32)
+            .collect();
+        if counts.iter().all(|&n| n == 0) {
+            // tiny budget, still worth a sample on the worst pixel
+            let (worst, _) = errors.iter().enumerate()
+                .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+                .unwrap();
+            counts[worst] = 1;
+        }
+        counts
+    }
+
+    // black (fewest samples) to red, yellow and white (most samples)
+    pub fn sample_heat_map(&self) -> FrameBuffer {
+        let (min, max) = self.sample_range();
+        let (min, max) = (min as Scalar, max as Scalar);
+        let mut img = FrameBuffer::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let t = if max > min { (self.get(x, y).samples as Scalar - min) / (max - min) } else { 0.0 };
+                let c = Color::new(
+                    (3.0 * t).clamp(0.0, 1.0),
+                    (3.0 * t - 1.0).clamp(0.0, 1.0),
+                    (3.0 * t - 2.0).clamp(0.0, 1.0));
+                img.set(x, y, c);
+            }
+        }
+        img
+    }
+
+    pub fn sample_range(&self) -> (u32, u32) {
+        let min = self.pixels.iter().map(|p| p.samples).min().unwrap_or(0);
+        let max = self.pixels.iter().map(|p| p.samples).max().unwrap_or(0);
+        (min, max)
+    }
+
+    // of the mean luminance of each pixel, row by row. A pixel of a single sample counts as
+    // having an error as large as its luminance.
+    pub fn variances(&self) -> Vec<Scalar> {
+        self.pixels.iter()
+            .map(|p| p.variance_of_mean().unwrap_or_else(|| color::luminance(p.mean()).powi(2)))
+            .collect()
+    }
+
+    pub fn resolve(&self, img: &mut FrameBuffer) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let splat = &self.splats[(y * self.width + x) as usize];
+                // the negative lobes of some filters can cancel the weight out at the image borders
+                let c = if splat.weight > 0.0 { splat.sum / splat.weight } else { self.get(x, y).mean() };
+                img.set(x, y, c);
+            }
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+use clap::ValueEnum;
+use exr::prelude::*;
+
+use crate::color::{self, Color};
+use crate::common::SP;
+use crate::framebuffer::FrameBuffer;
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{self, Point3, Scalar, Vec3};
+
+// Arbitrary output variables, what the camera rays see at their first hit
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Aov {
+    Albedo,
+    // shading normal, in world space
+    Normal,
+    Position,
+    // distance to the camera
+    Depth,
+    Uv,
+    MaterialId,
+    ObjectId,
+}
+
+impl Aov {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Position => "position",
+            Aov::Depth => "depth",
+            Aov::Uv => "uv",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+        }
+    }
+
+    // the ids cannot be averaged, they are taken from the first sample of the pixel
+    fn is_id(&self) -> bool {
+        matches!(self, Aov::MaterialId | Aov::ObjectId)
+    }
+}
+
+// Where the AOVs go
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum AovOutput {
+    // one image per AOV and format
+    Separate,
+    // layers of the exr output, the other formats still get separate images
+    Layers,
+}
+
+// Numbers of the materials, in the order the scene defines them, 0 for the unknown ones
+#[derive(Default)]
+pub struct MaterialIds {
+    ids: HashMap<usize, u32>,
+}
+
+impl MaterialIds {
+    fn key(mat: &SP<dyn Material>) -> usize {
+        SP::as_ptr(mat) as *const () as usize
+    }
+
+    pub fn register(&mut self, mat: &SP<dyn Material>) {
+        let next = self.ids.len() as u32 + 1;
+        self.ids.entry(Self::key(mat)).or_insert(next);
+    }
+
+    pub fn get(&self, mat: &SP<dyn Material>) -> u32 {
+        self.ids.get(&Self::key(mat)).copied().unwrap_or(0)
+    }
+}
+
+// distinct colors for consecutive ids, black for 0
+fn id_color(id: u32) -> Color {
+    if id == 0 {
+        return color::black();
+    }
+    let mut h = id.wrapping_mul(0x9e3779b9);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85ebca6b);
+    h ^= h >> 13;
+    let channel = |shift: u32| 0.2 + 0.8 * ((h >> shift) & 0xff) as Scalar / 255.0;
+    Color::new(channel(0), channel(8), channel(16))
+}
+
+// AOV values of one camera ray, black where it escapes the scene
+#[derive(Copy, Clone, Default)]
+pub struct AovSample {
+    albedo: Color,
+    normal: Vec3,
+    position: Point3,
+    depth: Scalar,
+    uv: Vec3,
+    material_id: u32,
+    object_id: u32,
+}
+
+impl AovSample {
+    pub fn new(ray: &Ray, rec: &HitRecord, material_ids: &MaterialIds) -> AovSample {
+        let mat = rec.mat.as_ref().unwrap();
+        AovSample {
+            albedo: mat.albedo(rec),
+            normal: rec.normal,
+            position: rec.p,
+            depth: rec.t * ray.direction().length(),
+            uv: rec.uv,
+            material_id: material_ids.get(mat),
+            object_id: rec.object_id,
+        }
+    }
+
+    fn value(&self, aov: Aov) -> Color {
+        match aov {
+            Aov::Albedo => self.albedo,
+            Aov::Normal => self.normal,
+            Aov::Position => self.position,
+            Aov::Depth => Vec3::uni(self.depth),
+            Aov::Uv => self.uv,
+            Aov::MaterialId => id_color(self.material_id),
+            Aov::ObjectId => id_color(self.object_id),
+        }
+    }
+}
+
+// Per pixel sums of the AOV samples of the first pass
+#[derive(Clone, Default)]
+pub struct AovPixel {
+    sum: Vec<Color>,
+    samples: u32,
+}
+
+impl AovPixel {
+    pub fn add(&mut self, aovs: &[Aov], sample: &AovSample) {
+        if self.sum.is_empty() {
+            self.sum = vec![color::black(); aovs.len()];
+        }
+        for (i, aov) in aovs.iter().enumerate() {
+            if aov.is_id() && self.samples > 0 {
+                continue;
+            }
+            self.sum[i] += sample.value(*aov);
+        }
+        self.samples += 1;
+    }
+
+    fn value(&self, aovs: &[Aov], i: usize) -> Color {
+        if self.samples == 0 || aovs[i].is_id() {
+            return self.sum.get(i).copied().unwrap_or_default();
+        }
+        self.sum[i] / self.samples as Scalar
+    }
+}
+
+// One image per requested AOV
+pub struct AovBuffers {
+    pub aovs: Vec<Aov>,
+    pub images: Vec<FrameBuffer>,
+}
+
+impl AovBuffers {
+    pub fn new(aovs: &[Aov], width: u32, height: u32) -> AovBuffers {
+        AovBuffers {
+            aovs: aovs.to_vec(),
+            images: aovs.iter().map(|_| FrameBuffer::new(width, height)).collect(),
+        }
+    }
+
+    pub fn get(&self, aov: Aov) -> Option<&FrameBuffer> {
+        self.aovs.iter().position(|a| *a == aov).map(|i| &self.images[i])
+    }
+
+    // drops the passes which are not in keep
+    pub fn retain(&mut self, keep: &[Aov]) {
+        let mut i = 0;
+        while i < self.aovs.len() {
+            if keep.contains(&self.aovs[i]) {
+                i += 1;
+            } else {
+                self.aovs.remove(i);
+                self.images.remove(i);
+            }
+        }
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, pixel: &AovPixel) {
+        for i in 0..self.aovs.len() {
+            self.images[i].set(x, y, pixel.value(&self.aovs, i));
+        }
+    }
+
+    // raw values squeezed into [0, 1] for the 8 bits formats
+    pub fn display_image(&self, i: usize) -> FrameBuffer {
+        let img = &self.images[i];
+        match self.aovs[i] {
+            Aov::Normal => {
+                let mut display = img.clone();
+                for y in 0..img.height {
+                    for x in 0..img.width {
+                        display.set(x, y, vec3::fit01(img.get(x, y)));
+                    }
+                }
+                display
+            }
+            Aov::Position | Aov::Depth => img.normalized(),
+            _ => img.clone(),
+        }
+    }
+}
+
+// Beauty in the R, G, B channels, the AOVs in channel groups named after them (albedo.R...)
+pub fn write_multilayer_exr(path: &Path, beauty: &FrameBuffer, aovs: &AovBuffers) -> io::Result<()> {
+    let size = Vec2(beauty.width as usize, beauty.height as usize);
+    let channel = |name: String, img: &FrameBuffer, c: usize| {
+        let mut samples = Vec::with_capacity(size.area());
+        for y in 0..img.height {
+            for x in 0..img.width {
+                samples.push(img.get(x, y).e[c] as f32);
+            }
+        }
+        AnyChannel::new(name.as_str(), FlatSamples::F32(samples))
+    };
+
+    let mut channels = Vec::new();
+    for (c, suffix) in ["R", "G", "B"].iter().enumerate() {
+        channels.push(channel(suffix.to_string(), beauty, c));
+        for (aov, img) in aovs.aovs.iter().zip(&aovs.images) {
+            channels.push(channel(format!("{}.{}", aov.name(), suffix), img, c));
+        }
+    }
+
+    let layer = Layer::new(
+        size,
+        LayerAttributes::default(),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(channels.into()),
+    );
+    Image::from_layer(layer).write().to_file(path)
+        .map_err(io::Error::other)
+}
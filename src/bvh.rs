@@ -0,0 +1,205 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::ray::Ray;
+use crate::vec3::Scalar;
+
+// Bounding volume hierarchy, objects are split at the median of their centroids
+// along the longest axis of the node
+pub struct BvhNode {
+    left: Box<dyn Hittable>,
+    right: Box<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    pub fn new(list: HittableList) -> BvhNode {
+        let mut objects = list.into_objects();
+        match objects.len() {
+            0 => BvhNode::from_pair(Box::new(HittableList::new()), Box::new(HittableList::new())),
+            1 => BvhNode::from_pair(objects.pop().unwrap(), Box::new(HittableList::new())),
+            _ => BvhNode::split(objects),
+        }
+    }
+
+    fn from_pair(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> BvhNode {
+        let bbox = Aabb::surrounding(&left.bounding_box(), &right.bounding_box());
+        BvhNode { left, right, bbox }
+    }
+
+    fn build(mut objects: Vec<Box<dyn Hittable>>) -> Box<dyn Hittable> {
+        if objects.len() == 1 {
+            return objects.pop().unwrap();
+        }
+        Box::new(BvhNode::split(objects))
+    }
+
+    fn split(mut objects: Vec<Box<dyn Hittable>>) -> BvhNode {
+        let centroid_bounds = objects.iter()
+            .map(|o| o.bounding_box().centroid())
+            .fold(Aabb::empty(), |acc, c| Aabb::surrounding(&acc, &Aabb::new(c, c)));
+        let axis = centroid_bounds.longest_axis();
+
+        let mid = objects.len() / 2;
+        objects.select_nth_unstable_by(mid, |a, b| {
+            let ca = a.bounding_box().centroid().e[axis];
+            let cb = b.bounding_box().centroid().e[axis];
+            ca.total_cmp(&cb)
+        });
+        let right = objects.split_off(mid);
+
+        BvhNode::from_pair(BvhNode::build(objects), BvhNode::build(right))
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, t_min: Scalar, t_max: Scalar, rec: &mut HitRecord) -> bool {
+        if !self.bbox.hit(ray, t_min, t_max) {
+            return false;
+        }
+
+        let hit_left = self.left.hit(ray, t_min, t_max, rec);
+        let closest_so_far = if hit_left { rec.t } else { t_max };
+        let hit_right = self.right.hit(ray, t_min, closest_so_far, rec);
+
+        hit_left || hit_right
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{self, random_double, Rng, SP};
+    use crate::material::{Lambertian, Material};
+    use crate::quad::Quad;
+    use crate::sphere::Sphere;
+    use crate::texture;
+    use crate::triangle::Triangle;
+    use crate::vec3::{Point3, Vec3};
+
+    fn random_point(rng: &mut Rng, size: Scalar) -> Point3 {
+        size * Vec3::new(2.0 * random_double(rng) - 1.0, 2.0 * random_double(rng) - 1.0, 2.0 * random_double(rng) - 1.0)
+    }
+
+    fn material(rng: &mut Rng) -> SP<dyn Material> {
+        SP::new(Lambertian::new(texture::solid(Vec3::new(random_double(rng), random_double(rng), random_double(rng)))))
+    }
+
+    // the same objects twice, one list to test linearly and one to build the hierarchy from
+    fn random_scene(rng: &mut Rng, count: usize) -> (HittableList, HittableList) {
+        let mut list = HittableList::new();
+        let mut copy = HittableList::new();
+        for i in 0..count {
+            let mat = material(rng);
+            let center = random_point(rng, 4.0);
+            let size = 0.1 + random_double(rng);
+            match i % 4 {
+                0 => {
+                    list.add(Box::new(Sphere::new(center, size, mat.clone())));
+                    copy.add(Box::new(Sphere::new(center, size, mat)));
+                }
+                1 => {
+                    let (u, v) = (random_point(rng, size), random_point(rng, size));
+                    list.add(Box::new(Quad::new(center, u, v, mat.clone())));
+                    copy.add(Box::new(Quad::new(center, u, v, mat)));
+                }
+                2 => {
+                    // flat bounding boxes, only hit once padded
+                    let (u, v) = match i % 3 {
+                        0 => (Vec3::new(size, 0.0, 0.0), Vec3::new(0.0, size, 0.0)),
+                        1 => (Vec3::new(0.0, size, 0.0), Vec3::new(0.0, 0.0, size)),
+                        _ => (Vec3::new(size, 0.0, 0.0), Vec3::new(0.0, 0.0, size)),
+                    };
+                    list.add(Box::new(Quad::new(center, u, v, mat.clone())));
+                    copy.add(Box::new(Quad::new(center, u, v, mat)));
+                }
+                _ => {
+                    let (b, c) = (center + random_point(rng, size), center + random_point(rng, size));
+                    list.add(Box::new(Triangle::new(center, b, c, mat.clone())));
+                    copy.add(Box::new(Triangle::new(center, b, c, mat)));
+                }
+            }
+        }
+        (list, copy)
+    }
+
+    fn random_ray(rng: &mut Rng) -> Ray {
+        let origin = random_point(rng, 8.0);
+        // towards the objects most of the time, some with axis aligned directions
+        let direction = match (random_double(rng) * 4.0) as u32 {
+            0 => Vec3::new(0.0, 0.0, -origin.z()),
+            1 => Vec3::new(-origin.x(), 0.0, 0.0),
+            _ => random_point(rng, 4.0) - origin,
+        };
+        Ray::new(origin, direction)
+    }
+
+    fn assert_same_hits(list: &HittableList, bvh: &BvhNode, rng: &mut Rng, rays: usize) -> usize {
+        let mut hits = 0;
+        for _ in 0..rays {
+            let ray = random_ray(rng);
+            let mut expected = HitRecord::new();
+            let mut actual = HitRecord::new();
+            let hit = list.hit(&ray, 0.001, common::INFINITY, &mut expected);
+            assert_eq!(hit, bvh.hit(&ray, 0.001, common::INFINITY, &mut actual));
+            if hit {
+                hits += 1;
+                assert_eq!(expected.t, actual.t);
+                assert_eq!(expected.p.e, actual.p.e);
+                assert!(SP::ptr_eq(expected.mat.as_ref().unwrap(), actual.mat.as_ref().unwrap()));
+            }
+        }
+        hits
+    }
+
+    #[test]
+    fn same_hits_as_the_list() {
+        let mut rng = common::seeded_rng(1);
+        for count in [2, 3, 17, 200] {
+            let (list, copy) = random_scene(&mut rng, count);
+            let bvh = BvhNode::new(copy);
+            let hits = assert_same_hits(&list, &bvh, &mut rng, 4000);
+            assert!(hits > 0);
+        }
+    }
+
+    #[test]
+    fn empty_list() {
+        let mut rng = common::seeded_rng(2);
+        let bvh = BvhNode::new(HittableList::new());
+        assert_eq!(assert_same_hits(&HittableList::new(), &bvh, &mut rng, 100), 0);
+    }
+
+    #[test]
+    fn single_object() {
+        let mut rng = common::seeded_rng(3);
+        for count in 1..=4 {
+            // one of each kind of object, the last one alone in the list
+            let (list, copy) = random_scene(&mut rng, count);
+            let (mut last, mut last_copy) = (HittableList::new(), HittableList::new());
+            last.add(list.into_objects().pop().unwrap());
+            last_copy.add(copy.into_objects().pop().unwrap());
+            let bvh = BvhNode::new(last_copy);
+            assert_same_hits(&last, &bvh, &mut rng, 2000);
+        }
+    }
+
+    #[test]
+    fn axis_aligned_quad() {
+        let mut rng = common::seeded_rng(4);
+        let mat = material(&mut rng);
+        let mut list = HittableList::new();
+        list.add(Box::new(Quad::new(Point3::new(-1.0, 0.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0), mat)));
+        let bvh = BvhNode::new(list);
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(Point3::new(0.2, 5.0, 0.3), Vec3::new(0.0, -1.0, 0.0));
+        assert!(bvh.hit(&ray, 0.001, common::INFINITY, &mut rec));
+        assert_eq!(rec.t, 5.0);
+        assert!(!bvh.hit(&Ray::new(Point3::new(2.0, 5.0, 0.3), Vec3::new(0.0, -1.0, 0.0)), 0.001, common::INFINITY, &mut rec));
+    }
+}
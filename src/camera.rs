@@ -1,10 +1,10 @@
-use chrono::offset;
-
 use crate::common;
+use crate::sampler::Sampler;
 use crate::ray::Ray;
 use crate::vec3;
 use crate::vec3::*;
 
+#[derive(Clone)]
 pub struct Camera
 {
     pub vfov: Scalar,
@@ -21,6 +21,8 @@ pub struct Camera
 
     u: Vec3,
     v: Vec3,
+    w: Vec3,
+    focus_dist: Scalar,
     lens_radius: Scalar,
 }
 
@@ -33,39 +35,43 @@ impl Camera {
         aperture: Scalar,
         focus_dist: Scalar,
     ) -> Camera {
-        let theta = common::degrees_to_radians(vfov);
-        let h = Scalar::tan(theta / 2.0);
-        let aspect_ratio = 16.0 / 9.0;
-        let viewport_height = 2.0 * h;
-        let viewport_width = aspect_ratio * viewport_height;
-
         let w = vec3::unit_vector(look_from - look_at);
         let u = vec3::unit_vector(vec3::cross(vup, w));
         let v = vec3::unit_vector(vec3::cross(w, u));
 
-        let origin = look_from;
-        let horizontal = focus_dist * viewport_width * u;
-        let vertical = focus_dist * viewport_height * v;
-        let lower_left_corner =
-            origin - horizontal / 2.0 - vertical / 2.0 - focus_dist * w;
-
-        let lens_radius = aperture / 2.0;
-
-        Camera {
+        let mut camera = Camera {
             vfov,
-            aspect_ratio,
-            origin,
-            horizontal,
-            vertical,
-            lower_left_corner,
+            aspect_ratio: 16.0 / 9.0,
+            origin: look_from,
+            horizontal: Vec3::default(),
+            vertical: Vec3::default(),
+            lower_left_corner: Vec3::default(),
             u,
             v,
-            lens_radius,
-        }
+            w,
+            focus_dist,
+            lens_radius: aperture / 2.0,
+        };
+        camera.set_aspect_ratio(camera.aspect_ratio);
+        camera
+    }
+
+    // width over height of the viewport, to match the one of the image
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: Scalar) {
+        let theta = common::degrees_to_radians(self.vfov);
+        let h = Scalar::tan(theta / 2.0);
+        let viewport_height = 2.0 * h;
+        let viewport_width = aspect_ratio * viewport_height;
+
+        self.aspect_ratio = aspect_ratio;
+        self.horizontal = self.focus_dist * viewport_width * self.u;
+        self.vertical = self.focus_dist * viewport_height * self.v;
+        self.lower_left_corner =
+            self.origin - self.horizontal / 2.0 - self.vertical / 2.0 - self.focus_dist * self.w;
     }
 
-    pub fn get_ray(&self, u: Scalar, v: Scalar) -> Ray {
-        let rd = self.lens_radius * vec3::random_in_unit_disk();
+    pub fn get_ray(&self, u: Scalar, v: Scalar, sampler: &mut dyn Sampler) -> Ray {
+        let rd = self.lens_radius * vec3::random_in_unit_disk(sampler);
         let offset = self.u * rd.x() + self.v * rd.y();
 
         let dir = self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin - offset;
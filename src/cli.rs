@@ -0,0 +1,301 @@
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+
+use crate::aov::{Aov, AovOutput};
+use crate::color::ToneMapping;
+use crate::filter::FilterKind;
+use crate::framebuffer::ImageFormat;
+use crate::integrator::IntegratorKind;
+use crate::sampler::SamplerKind;
+use crate::settings::Settings;
+use crate::tile::TileOrder;
+
+#[derive(Parser)]
+#[command(version, about = "Ray Tracing in One Weekend, in Rust")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Render a still image of a scene
+    Render(RenderArgs),
+    /// Render a sequence of frames while moving the camera
+    Animate {
+        #[command(flatten)]
+        render: RenderArgs,
+
+        /// Camera animation
+        #[arg(short, long, value_enum, default_value_t = Anim::Tournette)]
+        kind: Anim,
+
+        /// Number of frames
+        #[arg(short, long, default_value_t = 16, value_parser = clap::value_parser!(u32).range(2..))]
+        frames: u32,
+    },
+    /// Print the settings and content of a scene without rendering it
+    Info {
+        /// Scene description file
+        #[arg(default_value = DEFAULT_SCENE)]
+        scene: String,
+    },
+}
+
+pub const DEFAULT_SCENE: &str = "scenes/planes.toml";
+
+fn positive(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(x) if x > 0.0 => Ok(x),
+        Ok(_) => Err("must be positive".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(Args)]
+pub struct RenderArgs {
+    /// Scene description file
+    #[arg(short, long, default_value = DEFAULT_SCENE)]
+    pub scene: String,
+
+    /// Directory where images are written
+    #[arg(short, long, default_value = "output")]
+    pub output: String,
+
+    /// Image formats to write, exr and pfm keep the linear radiance
+    #[arg(short, long, value_enum, value_delimiter = ',', default_value = "png")]
+    pub format: Vec<ImageFormat>,
+
+    /// First hit passes to write along the image
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub aov: Vec<Aov>,
+
+    /// Separate images per pass, or layers of the exr output
+    #[arg(long, value_enum)]
+    pub aov_output: Option<AovOutput>,
+
+    /// Filter the noise out of the image, guided by the albedo and normal passes
+    #[arg(long)]
+    pub denoise: bool,
+
+    /// With --denoise, also write the image before denoising (render_last_raw)
+    #[arg(long)]
+    pub keep_raw: bool,
+
+    /// Tone mapping operator applied to the png output
+    #[arg(long, value_enum)]
+    pub tone_mapping: Option<ToneMapping>,
+
+    /// Exposure compensation in stops, before tone mapping
+    #[arg(long, allow_negative_numbers = true)]
+    pub exposure: Option<f64>,
+
+    /// Image width in pixels, the height follows the camera aspect ratio unless given
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub width: Option<u32>,
+
+    /// Image height in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub height: Option<u32>,
+
+    /// Number of samples per pixel
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub samples: Option<u32>,
+
+    /// Render in passes, sample_per_pixel becoming the sample budget
+    #[arg(long)]
+    pub progressive: bool,
+
+    /// Samples per pixel of each progressive pass
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub pass_samples: Option<u32>,
+
+    /// Spend the samples after the first min_samples on the noisiest pixels, implies --progressive
+    #[arg(long)]
+    pub adaptive: bool,
+
+    /// Samples given to every pixel before adaptive sampling starts, a quarter of the samples by default
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub min_samples: Option<u32>,
+
+    /// Also write an image of the number of samples per pixel
+    #[arg(long)]
+    pub sample_heat_map: bool,
+
+    /// Stop the progressive render after this many seconds, implies --progressive
+    #[arg(long, value_parser = positive)]
+    pub time_budget: Option<f64>,
+
+    /// Stop the progressive render once the relative error of every pixel is below it, implies --progressive
+    #[arg(long, value_parser = positive)]
+    pub noise_threshold: Option<f64>,
+
+    /// Seconds between two writes of the image during a progressive render
+    #[arg(long, value_parser = positive)]
+    pub write_interval: Option<f64>,
+
+    /// Maximum number of bounces of a ray
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_depth: Option<u32>,
+
+    /// Depth from which the paths leaving a diffuse bounce may be stopped by Russian roulette
+    #[arg(long)]
+    pub roulette_diffuse: Option<u32>,
+
+    /// Depth from which the paths leaving a specular reflection may be stopped by Russian roulette
+    #[arg(long)]
+    pub roulette_specular: Option<u32>,
+
+    /// Depth from which the paths leaving a refraction may be stopped by Russian roulette
+    #[arg(long)]
+    pub roulette_transmission: Option<u32>,
+
+    /// Side of the square tiles rendered by each thread, in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub tile_size: Option<u32>,
+
+    /// Order in which the tiles are rendered
+    #[arg(long, value_enum)]
+    pub tile_order: Option<TileOrder>,
+
+    /// What the image shows, path tracing or a debug view of the first hits
+    #[arg(long, value_enum)]
+    pub integrator: Option<IntegratorKind>,
+
+    /// Reach of the occlusion rays of the ambient-occlusion integrator
+    #[arg(long, value_parser = positive)]
+    pub ao_distance: Option<f64>,
+
+    /// Sample generator of the pixel, lens and bounce dimensions
+    #[arg(long, value_enum)]
+    pub sampler: Option<SamplerKind>,
+
+    /// Pixel reconstruction filter
+    #[arg(long, value_enum)]
+    pub filter: Option<FilterKind>,
+
+    /// Radius of the reconstruction filter in pixels, defaults to the usual one of the filter
+    #[arg(long, value_parser = positive)]
+    pub filter_radius: Option<f64>,
+
+    /// Seed of the random generator, the same seed gives the same image
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Render on all cores (true) or on a single thread (false)
+    #[arg(long)]
+    pub parallel: Option<bool>,
+}
+
+impl RenderArgs {
+    // command line values take precedence over the ones of the scene file,
+    // the settings they give together are checked as a whole
+    pub fn apply(&self, settings: &mut Settings) -> Result<(), clap::Error> {
+        if let Some(w) = self.width {
+            settings.set_image_width(w);
+        }
+        if let Some(h) = self.height {
+            settings.image_height = h;
+        }
+        settings.check_image_size()
+            .map_err(|message| Cli::command().error(ErrorKind::ValueValidation, message))?;
+        settings.fit_camera();
+        if let Some(spp) = self.samples {
+            settings.sample_per_pixel = spp;
+        }
+        if let Some(pass_samples) = self.pass_samples {
+            settings.pass_samples = pass_samples;
+        }
+        if let Some(budget) = self.time_budget {
+            settings.time_budget = Some(budget);
+        }
+        if let Some(threshold) = self.noise_threshold {
+            settings.noise_threshold = Some(threshold);
+        }
+        if let Some(interval) = self.write_interval {
+            settings.write_interval = interval;
+        }
+        if let Some(min_samples) = self.min_samples {
+            settings.min_samples = Some(min_samples);
+        }
+        if self.adaptive {
+            settings.adaptive = true;
+        }
+        if self.sample_heat_map {
+            settings.sample_heat_map = true;
+        }
+        if self.progressive || self.time_budget.is_some() || self.noise_threshold.is_some() {
+            settings.progressive = true;
+        }
+        if let Some(depth) = self.max_depth {
+            settings.max_depth = depth;
+        }
+        if let Some(depth) = self.roulette_diffuse {
+            settings.russian_roulette.diffuse = depth;
+        }
+        if let Some(depth) = self.roulette_specular {
+            settings.russian_roulette.specular = depth;
+        }
+        if let Some(depth) = self.roulette_transmission {
+            settings.russian_roulette.transmission = depth;
+        }
+        if let Some(tile_size) = self.tile_size {
+            settings.tile_size = tile_size;
+        }
+        if let Some(order) = self.tile_order {
+            settings.tile_order = order;
+        }
+        if let Some(seed) = self.seed {
+            settings.seed = seed;
+        }
+        if let Some(integrator) = self.integrator {
+            settings.integrator = integrator;
+        }
+        if let Some(distance) = self.ao_distance {
+            settings.ao_distance = distance;
+        }
+        if let Some(sampler) = self.sampler {
+            settings.sampler = sampler;
+        }
+        if let Some(kind) = self.filter {
+            settings.filter.kind = kind;
+        }
+        if let Some(radius) = self.filter_radius {
+            settings.filter.radius = Some(radius);
+        }
+        if let Some(parallel) = self.parallel {
+            settings.parallel = parallel;
+        }
+        if let Some(operator) = self.tone_mapping {
+            settings.tone_mapper.operator = operator;
+        }
+        if let Some(exposure) = self.exposure {
+            settings.tone_mapper.exposure = exposure;
+        }
+        if !self.aov.is_empty() {
+            settings.aovs = self.aov.clone();
+        }
+        if let Some(output) = self.aov_output {
+            settings.aov_output = output;
+        }
+        if self.denoise {
+            settings.denoise = true;
+        }
+        if self.keep_raw {
+            settings.keep_raw = true;
+        }
+        settings.output_dir = self.output.clone();
+        settings.output_formats = self.format.clone();
+        settings.check_min_samples()
+            .map_err(|message| Cli::command().error(ErrorKind::ArgumentConflict, message))
+    }
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+pub enum Anim {
+    Tournette,
+    Tournette2,
+    Fov,
+    Aperture,
+    DistFocus,
+}
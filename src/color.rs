@@ -1,6 +1,7 @@
 
 use crate::vec3::{Vec3, Scalar};
-use image::{Rgb};
+use clap::ValueEnum;
+use image::Rgb;
 
 // Type alias
 pub type Color = Vec3;
@@ -9,36 +10,83 @@ pub type PixelType = u8;
 
 fn to_byte(f: Scalar) -> PixelType
 {
-    return (255.999 * f) as PixelType;
+    // NaN goes to 0
+    (255.999 * f.clamp(0.0, 1.0)) as PixelType
 }
 
-pub trait WriteColor {
-    fn write_color(self, _: Color);
+pub fn luminance(c: Color) -> Scalar {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
 }
 
-impl WriteColor for &mut Rgb<PixelType> {
-    fn write_color(self, pixel_color: Color) {
-        *self = to_rgb(pixel_color)
+// sRGB transfer function, linear [0, 1] to display encoded [0, 1]
+pub fn linear_to_srgb(x: Scalar) -> Scalar {
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * Scalar::powf(x, 1.0 / 2.4) - 0.055
     }
 }
 
-impl WriteColor for &mut [u8] {
-    fn write_color(self, pixel_color: Color) {
-        self[0] = to_byte(pixel_color.x());
-        self[1] = to_byte(pixel_color.y());
-        self[2] = to_byte(pixel_color.z());
+pub fn srgb_to_linear(x: Scalar) -> Scalar {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        Scalar::powf((x + 0.055) / 1.055, 2.4)
     }
 }
 
-pub fn gamma(pixel_color: Color) -> Color {
-    Color::new( Scalar::sqrt(pixel_color.x()), 
-            Scalar::sqrt(pixel_color.y()), 
-            Scalar::sqrt(pixel_color.z()) )
+pub fn map_channels(c: Color, f: impl Fn(Scalar) -> Scalar) -> Color {
+    Color::new(f(c.x()), f(c.y()), f(c.z()))
 }
 
-pub fn to_rgb(pixel_color: Color) -> Rgb<PixelType> {
-    let pixel_color = gamma(pixel_color);
-    Rgb([to_byte(pixel_color.x()), to_byte(pixel_color.y()), to_byte(pixel_color.z())])
+#[derive(Copy, Clone, ValueEnum)]
+pub enum ToneMapping {
+    // values above 1 are clipped
+    Clamp,
+    // on the luminance, keeps the hue
+    Reinhard,
+    // Narkowicz fit of the ACES filmic curve
+    Aces,
+}
+
+// Radiance to displayable color
+#[derive(Copy, Clone)]
+pub struct ToneMapper {
+    pub operator: ToneMapping,
+    // in stops
+    pub exposure: Scalar,
+}
+
+impl Default for ToneMapper {
+    fn default() -> Self {
+        ToneMapper {
+            operator: ToneMapping::Clamp,
+            exposure: 0.0,
+        }
+    }
+}
+
+impl ToneMapper {
+    // linear, in [0, 1]
+    pub fn map(&self, radiance: Color) -> Color {
+        let c = Scalar::powf(2.0, self.exposure) * radiance;
+        let mapped = match self.operator {
+            ToneMapping::Clamp => c,
+            ToneMapping::Reinhard => {
+                let l = luminance(c);
+                if l <= 0.0 { c } else { (1.0 / (1.0 + l)) * c }
+            }
+            ToneMapping::Aces => map_channels(c, |x| {
+                (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+            }),
+        };
+        map_channels(mapped, |x| x.clamp(0.0, 1.0))
+    }
+
+    pub fn encode(&self, radiance: Color) -> Rgb<PixelType> {
+        let c = map_channels(self.map(radiance), linear_to_srgb);
+        Rgb([to_byte(c.x()), to_byte(c.y()), to_byte(c.z())])
+    }
 }
 
 pub const fn red() -> Color{
@@ -2,16 +2,20 @@
 use crate::vec3::Scalar;
 
 pub use std::f64::consts::PI;
-pub use std::f64::INFINITY;
+pub const INFINITY: Scalar = Scalar::INFINITY;
 
 use std::ops::Range;
 //use std::rc::Rc;
 use std::sync::Arc;
 
-use rand::Rng;
+use rand::{Rng as _, SeedableRng};
+use rand::rngs::SmallRng;
 
 pub type SP<T> = Arc<T>;
 
+// Random generator threaded through the renderer, never shared between threads
+pub type Rng = SmallRng;
+
 
 // Utility
 
@@ -19,26 +23,37 @@ pub fn degrees_to_radians(degrees: Scalar) -> Scalar {
     degrees * PI / 180.0
 }
 
-pub fn random_double() -> Scalar {
-    rand::thread_rng().gen()
+pub fn seeded_rng(seed: u64) -> Rng {
+    Rng::seed_from_u64(seed)
 }
 
-pub fn random_int(range: Range<u32>) -> u32 {
-    rand::thread_rng().gen_range(range)
+// splitmix64 finalizer
+pub fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
 }
 
-pub fn linear_step(t:Scalar, min: Scalar, max: Scalar) -> Scalar {
-    min + (max - min) * t
+// Independent stream for one sample of one pixel, whatever the thread computing it
+pub fn sample_rng(seed: u64, x: u32, y: u32, sample: u32) -> Rng {
+    let pixel = ((y as u64) << 32) | x as u64;
+    seeded_rng(mix(mix(mix(seed) ^ pixel) ^ sample as u64))
+}
+
+pub fn random_double(rng: &mut Rng) -> Scalar {
+    rng.gen()
 }
 
-pub fn random_double_range(min: Scalar, max: Scalar) -> Scalar {
-    linear_step(random_double(), min, max)
+pub fn random_int(rng: &mut Rng, range: Range<u32>) -> u32 {
+    rng.gen_range(range)
 }
 
-pub fn checkerboard(t: Scalar, size: Scalar) -> bool {
-    (((t / size).round() as i32) % 2) != 0
+pub fn linear_step(t:Scalar, min: Scalar, max: Scalar) -> Scalar {
+    min + (max - min) * t
 }
 
+
+#[allow(dead_code)]
 pub fn sigmoid(x: Scalar, lambda: Scalar) -> Scalar {
     1.0 / (1.0 + Scalar::exp(- lambda * x))
 }
\ No newline at end of file
@@ -0,0 +1,124 @@
+use rayon::prelude::*;
+
+use crate::color::{self, Color};
+use crate::framebuffer::FrameBuffer;
+use crate::vec3::{self, Scalar, Vec3};
+
+const ITERATIONS: u32 = 5;
+// B3 spline, the 5x5 kernel is its outer product
+const KERNEL: [Scalar; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// how many standard deviations of the noise two colors may differ by and still be mixed
+const SIGMA_COLOR: Scalar = 4.0;
+const SIGMA_ALBEDO: Scalar = 0.1;
+const NORMAL_POWER: i32 = 64;
+
+// Feature buffers of the first hits, and the noise of the pixels
+pub struct Guides<'a> {
+    pub albedo: &'a FrameBuffer,
+    pub normal: &'a FrameBuffer,
+    // of the mean luminance, row by row
+    pub variance: &'a [Scalar],
+}
+
+fn normal_weight(n0: Vec3, n1: Vec3) -> Scalar {
+    // escaping rays have no normal, they only mix with each other
+    match (n0.near_zero(), n1.near_zero()) {
+        (true, true) => 1.0,
+        // the normals of the pass are averaged over the pixel, shorter than 1 on silhouettes
+        (false, false) => vec3::dot(vec3::unit_vector(n0), vec3::unit_vector(n1)).max(0.0).powi(NORMAL_POWER),
+        _ => 0.0,
+    }
+}
+
+// One iteration of the edge avoiding a-trous wavelet filter, the taps are `step` pixels apart.
+// Colors are told apart relative to their noise : converged highlights and edges are kept,
+// noisy neighbours are mixed. The variance of the result follows the weights.
+fn filter_step(img: &FrameBuffer, variance: &[Scalar], guides: &Guides, step: i64) -> (FrameBuffer, Vec<Scalar>) {
+    let (width, height) = (img.width as i64, img.height as i64);
+    let rows: Vec<Vec<(Color, Scalar)>> = (0..height)
+        .into_par_iter()
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    let c0 = img.get(x as u32, y as u32);
+                    let v0 = variance[(y * width + x) as usize];
+                    let n0 = guides.normal.get(x as u32, y as u32);
+                    let a0 = guides.albedo.get(x as u32, y as u32);
+
+                    let mut sum = color::black();
+                    let mut sum_variance = 0.0;
+                    let mut total_weight = 0.0;
+                    for (j, ky) in KERNEL.iter().enumerate() {
+                        for (i, kx) in KERNEL.iter().enumerate() {
+                            let sx = x + (i as i64 - 2) * step;
+                            let sy = y + (j as i64 - 2) * step;
+                            if sx < 0 || sy < 0 || sx >= width || sy >= height {
+                                continue;
+                            }
+                            let v = variance[(sy * width + sx) as usize];
+                            let (sx, sy) = (sx as u32, sy as u32);
+                            let c = img.get(sx, sy);
+
+                            // the difference of two noisy pixels has the sum of their variances
+                            let noise = SIGMA_COLOR * SIGMA_COLOR * (v0 + v) + 1e-12;
+                            let w_color = Scalar::exp(-(c - c0).length_squared() / noise);
+                            let w_albedo = Scalar::exp(-(guides.albedo.get(sx, sy) - a0).length_squared() / (SIGMA_ALBEDO * SIGMA_ALBEDO));
+                            let w_normal = normal_weight(n0, guides.normal.get(sx, sy));
+                            let w = kx * ky * w_color * w_albedo * w_normal;
+
+                            sum += w * c;
+                            sum_variance += w * w * v;
+                            total_weight += w;
+                        }
+                    }
+                    if total_weight > 0.0 {
+                        (sum / total_weight, sum_variance / (total_weight * total_weight))
+                    } else {
+                        (c0, v0)
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut result = FrameBuffer::new(img.width, img.height);
+    let mut result_variance = Vec::with_capacity(variance.len());
+    for (y, row) in rows.iter().enumerate() {
+        for (x, (c, v)) in row.iter().enumerate() {
+            result.set(x as u32, y as u32, *c);
+            result_variance.push(*v);
+        }
+    }
+    (result, result_variance)
+}
+
+// The albedo is divided out before filtering and multiplied back after, so that the textures stay sharp
+pub fn denoise(img: &FrameBuffer, guides: &Guides) -> FrameBuffer {
+    let demodulation = |x: u32, y: u32| {
+        let a = guides.albedo.get(x, y);
+        Color::new(a.x().max(0.01), a.y().max(0.01), a.z().max(0.01))
+    };
+
+    let mut irradiance = FrameBuffer::new(img.width, img.height);
+    let mut variance = Vec::with_capacity(guides.variance.len());
+    for y in 0..img.height {
+        for x in 0..img.width {
+            let d = demodulation(x, y);
+            irradiance.set(x, y, img.get(x, y) / d);
+            variance.push(guides.variance[(y * img.width + x) as usize] / color::luminance(d).powi(2));
+        }
+    }
+
+    for i in 0..ITERATIONS {
+        (irradiance, variance) = filter_step(&irradiance, &variance, guides, 1 << i);
+    }
+
+    let mut result = FrameBuffer::new(img.width, img.height);
+    for y in 0..img.height {
+        for x in 0..img.width {
+            result.set(x, y, irradiance.get(x, y) * demodulation(x, y));
+        }
+    }
+    result
+}
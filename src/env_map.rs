@@ -0,0 +1,147 @@
+use std::path::Path;
+
+use crate::color::{self, Color};
+use crate::common;
+use crate::image_io::{self, ImageError};
+use crate::sampler::Sampler;
+use crate::vec3::{self, Scalar, Vec3};
+
+// Piecewise constant distribution over [0, 1)
+struct Distribution1D {
+    func: Vec<Scalar>,
+    cdf: Vec<Scalar>,
+    integral: Scalar,
+}
+
+impl Distribution1D {
+    fn new(func: Vec<Scalar>) -> Distribution1D {
+        let n = func.len() as Scalar;
+        let mut cdf = vec![0.0; func.len() + 1];
+        for i in 0..func.len() {
+            cdf[i + 1] = cdf[i] + func[i] / n;
+        }
+        let integral = cdf[func.len()];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            // uniform when everything is black
+            *c = if integral > 0.0 { *c / integral } else { i as Scalar / n };
+        }
+        Distribution1D { func, cdf, integral }
+    }
+
+    fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    // value in [0, 1), its density and the index of its segment
+    fn sample(&self, u: Scalar) -> (Scalar, Scalar, usize) {
+        let index = self.cdf.partition_point(|&c| c <= u).clamp(1, self.count()) - 1;
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let du = if width > 0.0 { (u - self.cdf[index]) / width } else { 0.0 };
+        let x = (index as Scalar + du) / self.count() as Scalar;
+        (x, self.pdf(index), index)
+    }
+
+    fn pdf(&self, index: usize) -> Scalar {
+        if self.integral > 0.0 { self.func[index] / self.integral } else { 1.0 }
+    }
+}
+
+// Equirectangular environment, +y is up
+pub struct EnvMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    // around the vertical axis, in radians
+    rotation: Scalar,
+    intensity: Scalar,
+
+    // importance sampling by luminance, one conditional distribution per row
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl EnvMap {
+    pub fn load(path: &Path, rotation_degrees: Scalar, intensity: Scalar) -> Result<EnvMap, ImageError> {
+        let (width, height, pixels) = image_io::load_pixels(path)?;
+        Ok(EnvMap::new(width, height, pixels, rotation_degrees, intensity))
+    }
+
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>, rotation_degrees: Scalar, intensity: Scalar) -> EnvMap {
+        // the sin accounts for the smaller solid angle of the rows near the poles
+        let conditional: Vec<Distribution1D> = (0..height)
+            .map(|y| {
+                let sin_theta = Scalar::sin(common::PI * (y as Scalar + 0.5) / height as Scalar);
+                Distribution1D::new((0..width)
+                    .map(|x| sin_theta * color::luminance(pixels[y * width + x]).max(0.0))
+                    .collect())
+            })
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.integral).collect());
+
+        EnvMap {
+            width,
+            height,
+            pixels,
+            rotation: common::degrees_to_radians(rotation_degrees),
+            intensity,
+            conditional,
+            marginal,
+        }
+    }
+
+    fn texel(&self, x: isize, y: isize) -> Color {
+        let x = x.rem_euclid(self.width as isize) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    fn direction_to_uv(&self, direction: Vec3) -> (Scalar, Scalar) {
+        let d = vec3::unit_vector(direction);
+        let theta = Scalar::acos(d.y().clamp(-1.0, 1.0));
+        let phi = Scalar::atan2(d.z(), d.x()) + self.rotation;
+        ((phi / (2.0 * common::PI)).rem_euclid(1.0), theta / common::PI)
+    }
+
+    fn uv_to_direction(&self, u: Scalar, v: Scalar) -> Vec3 {
+        let theta = v * common::PI;
+        let phi = u * 2.0 * common::PI - self.rotation;
+        Vec3::new(Scalar::sin(theta) * Scalar::cos(phi), Scalar::cos(theta), Scalar::sin(theta) * Scalar::sin(phi))
+    }
+
+    // bilinear, wraps around horizontally
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        let x = u * self.width as Scalar - 0.5;
+        let y = v * self.height as Scalar - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let (x0, y0) = (x0 as isize, y0 as isize);
+
+        let top = (1.0 - fx) * self.texel(x0, y0) + fx * self.texel(x0 + 1, y0);
+        let bottom = (1.0 - fx) * self.texel(x0, y0 + 1) + fx * self.texel(x0 + 1, y0 + 1);
+        self.intensity * ((1.0 - fy) * top + fy * bottom)
+    }
+
+    // direction picked proportionally to the luminance
+    pub fn random_direction(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let (r1, r2) = sampler.get_2d();
+        let (v, _, row) = self.marginal.sample(r1);
+        let (u, _, _) = self.conditional[row].sample(r2);
+        self.uv_to_direction(u, v)
+    }
+
+    // per solid angle
+    pub fn pdf_value(&self, direction: Vec3) -> Scalar {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = Scalar::sin(v * common::PI);
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let row = ((v * self.height as Scalar) as usize).min(self.height - 1);
+        let column = ((u * self.width as Scalar) as usize).min(self.width - 1);
+        let pdf_uv = self.marginal.pdf(row) * self.conditional[row].pdf(column);
+        pdf_uv / (2.0 * common::PI * common::PI * sin_theta)
+    }
+}
@@ -0,0 +1,97 @@
+use clap::ValueEnum;
+
+use crate::common;
+use crate::vec3::Scalar;
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    // B = C = 1/3
+    Mitchell,
+    // three lobes windowed sinc
+    Lanczos,
+}
+
+impl FilterKind {
+    pub fn default_radius(&self) -> Scalar {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+// Pixel reconstruction filter, separable, zero beyond radius (in pixels),
+// the usual radius of the kind unless given
+#[derive(Copy, Clone)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: Option<Scalar>,
+}
+
+impl Default for Filter {
+    // each sample only counts for its own pixel
+    fn default() -> Self {
+        Filter { kind: FilterKind::Box, radius: None }
+    }
+}
+
+fn sinc(x: Scalar) -> Scalar {
+    if x.abs() < 1e-5 { 1.0 } else { Scalar::sin(common::PI * x) / (common::PI * x) }
+}
+
+fn mitchell(x: Scalar) -> Scalar {
+    const B: Scalar = 1.0 / 3.0;
+    const C: Scalar = 1.0 / 3.0;
+    let x = x.abs();
+    if x < 1.0 {
+        ((12.0 - 9.0 * B - 6.0 * C) * x * x * x + (-18.0 + 12.0 * B + 6.0 * C) * x * x + (6.0 - 2.0 * B)) / 6.0
+    } else if x < 2.0 {
+        ((-B - 6.0 * C) * x * x * x + (6.0 * B + 30.0 * C) * x * x + (-12.0 * B - 48.0 * C) * x + (8.0 * B + 24.0 * C)) / 6.0
+    } else {
+        0.0
+    }
+}
+
+impl Filter {
+    pub fn radius(&self) -> Scalar {
+        self.radius.unwrap_or(self.kind.default_radius())
+    }
+
+    fn weight_1d(&self, x: Scalar) -> Scalar {
+        let x = x.abs();
+        let r = self.radius();
+        if x > r {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => r - x,
+            FilterKind::Gaussian => {
+                // sigma of half the radius, shifted to reach zero at the radius
+                let sigma2 = 0.25 * r * r;
+                (Scalar::exp(-x * x / (2.0 * sigma2)) - Scalar::exp(-r * r / (2.0 * sigma2))).max(0.0)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / r),
+            FilterKind::Lanczos => {
+                let t = 3.0 * x / r;
+                sinc(t) * sinc(t / 3.0)
+            }
+        }
+    }
+
+    // (dx, dy) from the sample to the pixel center
+    pub fn weight(&self, dx: Scalar, dy: Scalar) -> Scalar {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    // pixels around the one of a sample which it may reach
+    pub fn margin(&self) -> u32 {
+        (self.radius() - 0.5).max(0.0).ceil() as u32
+    }
+}
@@ -0,0 +1,119 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use clap::ValueEnum;
+use image::{ImageBuffer, RgbImage};
+
+use crate::color::{Color, ToneMapper};
+use crate::vec3::Scalar;
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum ImageFormat {
+    // 8 bits, display referred
+    Png,
+    // linear radiance, 32 bits float
+    Exr,
+    Pfm,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Exr => "exr",
+            ImageFormat::Pfm => "pfm",
+        }
+    }
+}
+
+// Linear radiance of the rendered image, row 0 at the top
+#[derive(Clone)]
+pub struct FrameBuffer {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<[f32; 3]>,
+}
+
+impl FrameBuffer {
+    pub fn new(width: u32, height: u32) -> FrameBuffer {
+        FrameBuffer {
+            width,
+            height,
+            pixels: vec![[0.0; 3]; (width * height) as usize],
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        let p = self.pixels[self.index(x, y)];
+        Color::new(p[0] as Scalar, p[1] as Scalar, p[2] as Scalar)
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, c: Color) {
+        let i = self.index(x, y);
+        self.pixels[i] = [c.x() as f32, c.y() as f32, c.z() as f32];
+    }
+
+    // every channel remapped from its [min, max] over the image to [0, 1]
+    pub fn normalized(&self) -> FrameBuffer {
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for p in &self.pixels {
+            for c in 0..3 {
+                min[c] = min[c].min(p[c]);
+                max[c] = max[c].max(p[c]);
+            }
+        }
+        let mut img = self.clone();
+        for p in &mut img.pixels {
+            for c in 0..3 {
+                p[c] = if max[c] > min[c] { (p[c] - min[c]) / (max[c] - min[c]) } else { 0.0 };
+            }
+        }
+        img
+    }
+
+    pub fn to_rgb_image(&self, tone_mapper: &ToneMapper) -> RgbImage {
+        let mut img: RgbImage = ImageBuffer::new(self.width, self.height);
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            *pixel = tone_mapper.encode(self.get(x, y));
+        }
+        img
+    }
+
+    pub fn write_exr(&self, path: &Path) -> io::Result<()> {
+        exr::prelude::write_rgb_file(path, self.width as usize, self.height as usize, |x, y| {
+            let p = self.pixels[self.index(x as u32, y as u32)];
+            (p[0], p[1], p[2])
+        })
+        .map_err(io::Error::other)
+    }
+
+    // portable float map, little endian, rows stored bottom to top
+    pub fn write_pfm(&self, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        write!(file, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                for channel in self.pixels[self.index(x, y)] {
+                    file.write_all(&channel.to_le_bytes())?;
+                }
+            }
+        }
+        file.flush()
+    }
+
+    // the tone mapper only applies to the 8 bits formats
+    pub fn save(&self, path: &Path, format: ImageFormat, tone_mapper: &ToneMapper) -> io::Result<()> {
+        match format {
+            ImageFormat::Png => self.to_rgb_image(tone_mapper).save(path)
+                .map_err(io::Error::other),
+            ImageFormat::Exr => self.write_exr(path),
+            ImageFormat::Pfm => self.write_pfm(path),
+        }
+    }
+}
@@ -1,4 +1,6 @@
+use crate::aabb::Aabb;
 use crate::common::SP;
+use crate::sampler::Sampler;
 use crate::ray::Ray;
 use crate::vec3::*;
 use crate::material::Material;
@@ -11,6 +13,8 @@ pub struct HitRecord {
     pub t: Scalar,
     pub front_face: bool,
     pub uv: Vec3,
+    // set by Tagged, 0 for untagged objects
+    pub object_id: u32,
 }
 
 impl HitRecord {
@@ -30,4 +34,40 @@ impl HitRecord {
 
 pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, t_min: Scalar, t_max: Scalar, rec: &mut HitRecord) -> bool;
+    fn bounding_box(&self) -> Aabb;
+
+    // light sampling : density of the directions returned by random_direction, per solid angle
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> Scalar {
+        0.0
+    }
+
+    fn random_direction(&self, _origin: Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+}
+
+// Stamps the id of an object of the scene on its hits, for the object id pass
+pub struct Tagged {
+    object: Box<dyn Hittable>,
+    id: u32,
+}
+
+impl Tagged {
+    pub fn new(object: Box<dyn Hittable>, id: u32) -> Tagged {
+        Tagged { object, id }
+    }
+}
+
+impl Hittable for Tagged {
+    fn hit(&self, ray: &Ray, t_min: Scalar, t_max: Scalar, rec: &mut HitRecord) -> bool {
+        if !self.object.hit(ray, t_min, t_max, rec) {
+            return false;
+        }
+        rec.object_id = self.id;
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.object.bounding_box()
+    }
 }
\ No newline at end of file
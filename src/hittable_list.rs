@@ -1,10 +1,13 @@
+use crate::aabb::Aabb;
 use crate::hittable::{HitRecord, Hittable};
+use crate::sampler::Sampler;
 use crate::ray::Ray;
-use crate::vec3::Scalar;
+use crate::vec3::{Point3, Scalar, Vec3};
 
 #[derive(Default)]
 pub struct HittableList {
     objects: Vec<Box<dyn Hittable>>,
+    bbox: Aabb,
 }
 
 impl HittableList {
@@ -13,8 +16,21 @@ impl HittableList {
     }
 
     pub fn add(&mut self, object: Box<dyn Hittable>) {
+        self.bbox = Aabb::surrounding(&self.bbox, &object.bounding_box());
         self.objects.push(object);
     }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn into_objects(self) -> Vec<Box<dyn Hittable>> {
+        self.objects
+    }
 }
 
 impl Hittable for HittableList {
@@ -32,4 +48,24 @@ impl Hittable for HittableList {
         }
         hit_anything
     }
-}
\ No newline at end of file
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // objects are picked uniformly
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> Scalar {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let sum: Scalar = self.objects.iter()
+            .map(|o| o.pdf_value(origin, direction))
+            .sum();
+        sum / (self.objects.len() as Scalar)
+    }
+
+    fn random_direction(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let index = ((sampler.get_1d() * self.objects.len() as Scalar) as usize).min(self.objects.len() - 1);
+        self.objects[index].random_direction(origin, sampler)
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use image::ColorType;
+
+use crate::color::{self, Color};
+use crate::vec3::Scalar;
+
+// Linear pixels of the environment maps and image textures
+
+#[derive(Debug)]
+pub struct ImageError(String);
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+fn load_pixels_exr(path: &Path) -> Result<(usize, usize, Vec<Color>), ImageError> {
+    let image = exr::prelude::read_first_rgba_layer_from_file(
+        path,
+        |resolution, _| (resolution.width(), resolution.height(), vec![Color::default(); resolution.area()]),
+        |(width, _, pixels), pos, (r, g, b, _a): (f32, f32, f32, f32)| {
+            pixels[pos.y() * *width + pos.x()] = Color::new(r.into(), g.into(), b.into());
+        },
+    ).map_err(|e| ImageError(format!("cannot read {}: {}", path.display(), e)))?;
+
+    Ok(image.layer_data.channel_data.pixels)
+}
+
+fn load_pixels_pfm(path: &Path) -> Result<(usize, usize, Vec<Color>), ImageError> {
+    let error = |message: &str| ImageError(format!("cannot read {}: {}", path.display(), message));
+    let bytes = fs::read(path).map_err(|e| error(&e.to_string()))?;
+
+    // three whitespace separated header tokens : PF, width height, scale
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while tokens.len() < 4 && pos < bytes.len() {
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        let start = pos;
+        while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        tokens.push(String::from_utf8_lossy(&bytes[start..pos]).to_string());
+    }
+    pos += 1;
+    if tokens.len() < 4 || tokens[0] != "PF" {
+        return Err(error("not a color pfm file"));
+    }
+    let width: usize = tokens[1].parse().map_err(|_| error("malformed width"))?;
+    let height: usize = tokens[2].parse().map_err(|_| error("malformed height"))?;
+    let scale: f32 = tokens[3].parse().map_err(|_| error("malformed scale"))?;
+    let size = width.checked_mul(height)
+        .and_then(|n| n.checked_mul(12))
+        .ok_or_else(|| error("malformed size"))?;
+    let data = bytes.get(pos..).filter(|d| d.len() >= size)
+        .ok_or_else(|| error("truncated file"))?;
+
+    let read = |i: usize| {
+        let b = [data[4 * i], data[4 * i + 1], data[4 * i + 2], data[4 * i + 3]];
+        (if scale < 0.0 { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) }) as Scalar
+    };
+    let mut pixels = vec![Color::default(); width * height];
+    for y in 0..height {
+        // stored bottom to top
+        let row = height - 1 - y;
+        for x in 0..width {
+            let i = 3 * (row * width + x);
+            pixels[y * width + x] = Color::new(read(i), read(i + 1), read(i + 2));
+        }
+    }
+    Ok((width, height, pixels))
+}
+
+// the integer formats (png, jpeg...) are sRGB encoded, the float ones (hdr) are linear
+fn load_pixels_image(path: &Path) -> Result<(usize, usize, Vec<Color>), ImageError> {
+    let image = image::open(path)
+        .map_err(|e| ImageError(format!("cannot read {}: {}", path.display(), e)))?;
+    let srgb = !matches!(image.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+    let image = image.to_rgb32f();
+    let pixels = image.pixels()
+        .map(|p| Color::new(p.0[0].into(), p.0[1].into(), p.0[2].into()))
+        .map(|c| if srgb { color::map_channels(c, color::srgb_to_linear) } else { c })
+        .collect();
+    Ok((image.width() as usize, image.height() as usize, pixels))
+}
+
+// exr and pfm, or anything the image crate reads (hdr, png...), as linear values
+pub fn load_pixels(path: &Path) -> Result<(usize, usize, Vec<Color>), ImageError> {
+    let extension = path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let (width, height, pixels) = match extension.as_str() {
+        "exr" => load_pixels_exr(path)?,
+        "pfm" => load_pixels_pfm(path)?,
+        _ => load_pixels_image(path)?,
+    };
+    if width == 0 || height == 0 {
+        return Err(ImageError(format!("{} is empty", path.display())));
+    }
+    Ok((width, height, pixels))
+}
@@ -0,0 +1,256 @@
+use clap::ValueEnum;
+
+use crate::color::{self, Color};
+use crate::common;
+use crate::env_map::EnvMap;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::ray::Ray;
+use crate::render::RenderScene;
+use crate::sampler::Sampler;
+use crate::settings::{Background, Settings};
+use crate::vec3::{self, Scalar, Vec3};
+
+// What the color of a camera sample is made of
+pub trait Integrator: Send + Sync {
+    fn radiance(&self, ray: Ray, scene: &RenderScene, settings: &Settings, sampler: &mut dyn Sampler) -> Color;
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum IntegratorKind {
+    // the actual image
+    Path,
+    // the debug ones only look at the first hit, black where the camera rays escape
+    Normals,
+    AmbientOcclusion,
+    Depth,
+    Uv,
+}
+
+impl IntegratorKind {
+    pub fn integrator(&self, settings: &Settings) -> Box<dyn Integrator> {
+        match self {
+            IntegratorKind::Path => Box::new(PathTracer),
+            IntegratorKind::Normals => Box::new(NormalsIntegrator),
+            IntegratorKind::AmbientOcclusion => Box::new(AmbientOcclusion { distance: settings.ao_distance }),
+            IntegratorKind::Depth => Box::new(DepthIntegrator),
+            IntegratorKind::Uv => Box::new(UvIntegrator),
+        }
+    }
+}
+
+fn sky_color(ray: &Ray) -> Color {
+    let unit_direction = vec3::unit_vector(ray.direction());
+    let mut t = 0.5 * (unit_direction.y() + 1.0);
+    const COLOR1: Color = color::white();
+    const COLOR2: Color = Color::new(0.5, 0.7, 1.0);
+    //t = t * t * t;
+    //t = Scalar::powf(t, 0.5);
+    t*=1.5;
+    //t = sigmoid(t+0.5, 2.0);
+    
+    (1.0 - t) * COLOR1 + t * COLOR2
+}
+
+fn background_color(ray: &Ray, settings: &Settings) -> Color {
+    match &settings.background {
+        Background::Sky => sky_color(ray),
+        Background::Uniform(c) => *c,
+        Background::EnvMap(env_map) => env_map.radiance(ray.direction()),
+    }
+}
+
+// Emissive objects and environment map sampled for direct lighting, with the same probability
+struct LightSampler<'a> {
+    lights: Option<&'a HittableList>,
+    env_map: Option<&'a EnvMap>,
+}
+
+impl<'a> LightSampler<'a> {
+    fn new(settings: &'a Settings, lights: &'a HittableList) -> LightSampler<'a> {
+        if !settings.light_sampling {
+            return LightSampler { lights: None, env_map: None };
+        }
+        LightSampler {
+            lights: if lights.is_empty() { None } else { Some(lights) },
+            env_map: match &settings.background {
+                Background::EnvMap(env_map) => Some(env_map),
+                _ => None,
+            },
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.lights.is_none() && self.env_map.is_none()
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Scalar {
+        match (self.lights, self.env_map) {
+            (Some(lights), Some(env_map)) => 0.5 * (lights.pdf_value(origin, direction) + env_map.pdf_value(direction)),
+            (Some(lights), None) => lights.pdf_value(origin, direction),
+            (None, Some(env_map)) => env_map.pdf_value(direction),
+            (None, None) => 0.0,
+        }
+    }
+
+    fn random_direction(&self, origin: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        match (self.lights, self.env_map) {
+            (Some(lights), Some(env_map)) => {
+                if sampler.get_1d() < 0.5 { lights.random_direction(origin, sampler) } else { env_map.random_direction(sampler) }
+            }
+            (Some(lights), None) => lights.random_direction(origin, sampler),
+            (None, Some(env_map)) => env_map.random_direction(sampler),
+            (None, None) => Vec3::new(1.0, 0.0, 0.0),
+        }
+    }
+}
+
+pub const EPSILON: Scalar = 0.0001;
+
+// multiple importance sampling weight of a sample drawn with pdf_a
+fn power_heuristic(pdf_a: Scalar, pdf_b: Scalar) -> Scalar {
+    let a2 = pdf_a * pdf_a;
+    let b2 = pdf_b * pdf_b;
+    if a2 + b2 <= 0.0 { 0.0 } else { a2 / (a2 + b2) }
+}
+
+// next event estimation : direct light through a direction sampled on the lights
+fn sample_lights(ray: &Ray, rec: &HitRecord, world: &dyn Hittable, lights: &LightSampler, sampler: &mut dyn Sampler) -> Color {
+    let mat = rec.mat.as_ref().unwrap();
+
+    let shadow_ray = Ray::new(rec.p, lights.random_direction(rec.p, sampler));
+    let light_pdf = lights.pdf_value(rec.p, shadow_ray.direction());
+    if light_pdf <= 0.0 {
+        return color::black();
+    }
+    let bsdf_pdf = mat.pdf(ray, rec, &shadow_ray);
+    if bsdf_pdf <= 0.0 {
+        return color::black();
+    }
+
+    let mut light_rec = HitRecord::new();
+    let emitted = if world.hit(&shadow_ray, EPSILON, common::INFINITY, &mut light_rec) {
+        // black when occluded by a non emissive object
+        light_rec.mat.as_ref().unwrap().emitted(&light_rec)
+    } else {
+        match lights.env_map {
+            Some(env_map) => env_map.radiance(shadow_ray.direction()),
+            None => color::black(),
+        }
+    };
+
+    power_heuristic(light_pdf, bsdf_pdf) / light_pdf * mat.eval(ray, rec, &shadow_ray) * emitted
+}
+
+// Path tracing with next event estimation : bounce after bounce until the path escapes, is absorbed,
+// loses the Russian roulette or reaches max_depth
+pub struct PathTracer;
+
+impl Integrator for PathTracer {
+    fn radiance(&self, mut ray: Ray, scene: &RenderScene, settings: &Settings, sampler: &mut dyn Sampler) -> Color {
+        let world = scene.world;
+        let lights = &LightSampler::new(settings, scene.lights);
+        let mut radiance = color::black();
+        let mut throughput = color::white();
+        // density of the sample which gave `ray`, None for camera rays and specular bounces
+        let mut bsdf_pdf: Option<Scalar> = None;
+
+        for depth in 0..settings.max_depth {
+            let mut rec = HitRecord::new();
+            if !world.hit(&ray, EPSILON, common::INFINITY, &mut rec) {
+                let background = background_color(&ray, settings);
+                let weight = match (bsdf_pdf, lights.env_map) {
+                    (Some(pdf), Some(_)) => power_heuristic(pdf, lights.pdf_value(ray.origin(), ray.direction())),
+                    _ => 1.0,
+                };
+                radiance += throughput * weight * background;
+                break;
+            }
+
+            let mat = rec.mat.as_ref().unwrap();
+            let mut emitted = mat.emitted(&rec);
+            if let Some(pdf) = bsdf_pdf {
+                // the other part of this light was gathered by sample_lights at the previous bounce
+                emitted = power_heuristic(pdf, lights.pdf_value(ray.origin(), ray.direction())) * emitted;
+            }
+            radiance += throughput * emitted;
+
+            let Some(srec) = mat.scatter(&ray, &rec, sampler) else {
+                break;
+            };
+            if lights.is_empty() || srec.is_specular() {
+                bsdf_pdf = None;
+            } else {
+                radiance += throughput * sample_lights(&ray, &rec, world, lights, sampler);
+                bsdf_pdf = Some(srec.pdf);
+            }
+            throughput = throughput * srec.attenuation;
+
+            if depth + 1 >= settings.russian_roulette.min_depth(srec.lobe) {
+                let survival = throughput.x().max(throughput.y()).max(throughput.z()).min(1.0);
+                if survival <= 0.0 || sampler.get_1d() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+            ray = srec.scattered;
+        }
+        radiance
+    }
+}
+
+fn first_hit(ray: &Ray, scene: &RenderScene) -> Option<HitRecord> {
+    let mut rec = HitRecord::new();
+    scene.world.hit(ray, EPSILON, common::INFINITY, &mut rec).then_some(rec)
+}
+
+// shading normal mapped from [-1, 1] to [0, 1]
+pub struct NormalsIntegrator;
+
+impl Integrator for NormalsIntegrator {
+    fn radiance(&self, ray: Ray, scene: &RenderScene, _settings: &Settings, _sampler: &mut dyn Sampler) -> Color {
+        first_hit(&ray, scene).map_or(color::black(), |rec| vec3::fit01(rec.normal))
+    }
+}
+
+// Fraction of the cosine weighted hemisphere left open within distance, white where the camera rays escape
+pub struct AmbientOcclusion {
+    pub distance: Scalar,
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(&self, ray: Ray, scene: &RenderScene, _settings: &Settings, sampler: &mut dyn Sampler) -> Color {
+        let Some(rec) = first_hit(&ray, scene) else {
+            return color::white();
+        };
+        let mut direction = rec.normal + vec3::random_unit_vector(sampler);
+        if direction.near_zero() {
+            direction = rec.normal;
+        }
+        let occlusion_ray = Ray::new(rec.p, vec3::unit_vector(direction));
+        let mut occluder = HitRecord::new();
+        if scene.world.hit(&occlusion_ray, EPSILON, self.distance, &mut occluder) {
+            color::black()
+        } else {
+            color::white()
+        }
+    }
+}
+
+// distance to the camera, unnormalized : exr keeps it as is
+pub struct DepthIntegrator;
+
+impl Integrator for DepthIntegrator {
+    fn radiance(&self, ray: Ray, scene: &RenderScene, _settings: &Settings, _sampler: &mut dyn Sampler) -> Color {
+        first_hit(&ray, scene).map_or(color::black(), |rec| Vec3::uni(rec.t * ray.direction().length()))
+    }
+}
+
+// texture coordinates in the red and green channels
+pub struct UvIntegrator;
+
+impl Integrator for UvIntegrator {
+    fn radiance(&self, ray: Ray, scene: &RenderScene, _settings: &Settings, _sampler: &mut dyn Sampler) -> Color {
+        first_hit(&ray, scene).map_or(color::black(), |rec| rec.uv)
+    }
+}
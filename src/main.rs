@@ -1,4 +1,6 @@
 mod vec3;
+mod aabb;
+mod bvh;
 mod settings;
 mod color;
 mod ray;
@@ -7,258 +9,239 @@ mod hittable;
 mod hittable_list;
 mod common;
 mod material;
+mod microfacet;
 mod noise;
+mod onb;
 
+mod cli;
 mod render;
+mod framebuffer;
+mod scene;
 mod sphere;
 mod quad;
-use crate::render::render;
+mod triangle;
+mod obj;
+mod env_map;
+mod image_io;
+mod tile;
+mod accumulator;
+mod aov;
+mod denoise;
+mod filter;
+mod sampler;
+mod integrator;
+mod texture;
+use crate::render::{render, RenderScene};
+use crate::aov::{Aov, AovBuffers, AovOutput};
+use crate::cli::{Anim, Cli, Command};
 
 use std::path::Path;
 use std::fs;
 use camera::Camera;
-use common::degrees_to_radians;
-use image::{ImageBuffer, RgbImage};
+use framebuffer::{FrameBuffer, ImageFormat};
 use settings::Settings;
+use bvh::BvhNode;
 use vec3::*;
 use common::*;
 
 use std::time::Instant;
 use chrono::prelude::*;
+use clap::Parser;
 
-fn launch_render(settings: &Settings) -> std::io::Result<()> {
+fn launch_render(settings: &Settings, scene: &RenderScene) -> std::io::Result<()> {
 
     let chrono_total = Instant::now();
 
-    let mut img: RgbImage = ImageBuffer::new(settings.image_width, settings.image_height);
+    fs::create_dir_all(&settings.output_dir)?;
+    // the denoiser needs the albedo and normal passes, even when they are not written
+    let mut render_settings = settings.clone();
+    if settings.denoise {
+        for guide in [Aov::Albedo, Aov::Normal] {
+            if !render_settings.aovs.contains(&guide) {
+                render_settings.aovs.push(guide);
+            }
+        }
+    }
+
+    let mut img = FrameBuffer::new(settings.image_width, settings.image_height);
+    let mut aovs = AovBuffers::new(&render_settings.aovs, settings.image_width, settings.image_height);
     settings.dump();
 
+    let last_filename = |format: &ImageFormat| Path::new(&settings.output_dir)
+        .join(format!("render_last.{}", format.extension()));
+    // progressive renders overwrite it with the image so far
+    let mut write_last = |img: &FrameBuffer| -> std::io::Result<()> {
+        for format in &settings.output_formats {
+            img.save(&last_filename(format), *format, &settings.tone_mapper)?;
+        }
+        Ok(())
+    };
+
     println!("Rendering...");
     let chrono_render_loop = Instant::now();
-    render(&settings, &mut img);
+    let acc = render(&render_settings, scene, &mut img, &mut aovs, &mut write_last)?;
     println!("== Elapsed render {:?}", chrono_render_loop.elapsed());
 
-    
+    if settings.sample_heat_map {
+        let (min, max) = acc.sample_range();
+        let heat_map_filename = Path::new(&settings.output_dir).join("render_last_samples.png");
+        println!("Samples per pixel from {} to {}, saving {}...", min, max, heat_map_filename.display());
+        acc.sample_heat_map().save(&heat_map_filename, ImageFormat::Png, &Default::default())?;
+    }
+
+    if settings.denoise {
+        if settings.keep_raw {
+            for format in &settings.output_formats {
+                let raw_filename = Path::new(&settings.output_dir)
+                    .join(format!("render_last_raw.{}", format.extension()));
+                img.save(&raw_filename, *format, &settings.tone_mapper)?;
+            }
+        }
+        let chrono_denoise = Instant::now();
+        let variance = acc.variances();
+        let guides = denoise::Guides {
+            albedo: aovs.get(Aov::Albedo).unwrap(),
+            normal: aovs.get(Aov::Normal).unwrap(),
+            variance: &variance,
+        };
+        img = denoise::denoise(&img, &guides);
+        println!("== Elapsed denoise {:?}", chrono_denoise.elapsed());
+        aovs.retain(&settings.aovs);
+    }
+
     let chrono_save = Instant::now();
-    let output_filename_last="output/render_last.png";
-    img.save(Path::new(output_filename_last)).unwrap();
+    write_last(&img)?;
+    for format in &settings.output_formats {
+        if *format == ImageFormat::Exr && settings.aov_output == AovOutput::Layers {
+            aov::write_multilayer_exr(&last_filename(format), &img, &aovs)?;
+            continue;
+        }
+        for (i, aov) in aovs.aovs.iter().enumerate() {
+            let path = Path::new(&settings.output_dir)
+                .join(format!("render_last_{}.{}", aov.name(), format.extension()));
+            match format {
+                ImageFormat::Png => aovs.display_image(i).save(&path, *format, &Default::default())?,
+                _ => aovs.images[i].save(&path, *format, &Default::default())?,
+            }
+        }
+    }
     println!("== Elapsed save {:?}", chrono_save.elapsed());
 
     let local: DateTime<Local> = Local::now();
-    let output_filename=format!("output/render_{}.png", local.format("%Y-%m-%d_%H_%M_%S_%3f").to_string());
-    println!("Saving {}...", output_filename);
-    fs::copy(output_filename_last, output_filename)?;
+    let timestamp = local.format("%Y-%m-%d_%H_%M_%S_%3f");
+    for format in &settings.output_formats {
+        let output_filename_last = last_filename(format);
+        let output_filename = Path::new(&settings.output_dir)
+            .join(format!("render_{}.{}", timestamp, format.extension()));
+        println!("Saving {}...", output_filename.display());
+        fs::copy(output_filename_last, output_filename)?;
+    }
 
     println!("= Elapsed render+save {:?}", chrono_total.elapsed());
 
     Ok(())
 }
 
-enum Anim {
-    Still,
-    StillCamBase,
-    Tournette,
-    Tournette2,
-    Fov,
-    Aperture,
-    DistFocus,
+fn look_around(look_at: Point3, dist: Scalar, t: Scalar) -> Point3 {
+    look_at + 
+        Point3::new(dist * Scalar::cos(t), 
+                    0.8, 
+                    dist * Scalar::sin(t) )
 }
 
-fn main() -> std::io::Result<()> {
-
-    let anim = Anim::StillCamBase;
-    let nb_frames = 16;
+fn anim_camera(anim: Anim, i: u32, nb_frames: u32) -> Camera {
+    let look_at = Point3::new(0.0, 0.0, -1.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
 
     match anim {
-        Anim::Still => {
-            let mut settings: settings::Settings = Default::default();
-
-            let fov = 50.0;
-            let dist = 2.0;
-            let look_at = Point3::new(0.0, 0.0, -1.0);
-            let t = 2.0 * PI *(1.0 / 6.0);
-            let look_from = look_at + 
-                Point3::new(dist * Scalar::cos(t), 
-                            0.8, 
-                            dist * Scalar::sin(t) );
-            let dist_to_focus = (look_from - look_at).length();
-            //let aperture = 0.3;
-            let aperture = 0.0;
-                    
-            settings.camera = Camera::new(
-                look_from,
-                look_at,
-                Vec3::new(0.0, 1.0, 0.0),
-                fov, 
-                aperture,
-            dist_to_focus);
-                
-            launch_render(&settings)?;   
-        }
-        Anim::StillCamBase => {
-            let mut settings: settings::Settings = Default::default();
-
-            let fov = 50.0;
-            let dist = 2.0;
-            let look_at = Point3::new(0.0, 0.0, -1.0);
-            let t = 2.0 * PI *(1.0 / 4.0);
-            let look_from = look_at + 
-                Point3::new(dist * Scalar::cos(t), 
-                            0.8, 
-                            dist * Scalar::sin(t) );
-            let dist_to_focus = (look_from - look_at).length() - 0.4;
-            //let aperture = 0.3;
-            let aperture = 0.0;
-                    
-            settings.camera = Camera::new(
-                look_from,
-                look_at,
-                Vec3::new(0.0, 1.0, 0.0),
-                fov, 
-                aperture,
-            dist_to_focus);
-                
-            launch_render(&settings)?;   
-        }
         Anim::Tournette => {
-            for i in 0..nb_frames {
-                let mut settings: settings::Settings = Default::default();
-
-                let fov = 30.0;
-                let dist = 5.0;
-                let look_at = Point3::new(0.0, 0.0, -1.0);
-                let t = 2.0 * PI *(i as Scalar) / (nb_frames as Scalar);
-                let look_from = look_at + 
-                    Point3::new(dist * Scalar::cos(t), 
-                                0.8, 
-                                dist * Scalar::sin(t) );
-                let dist_to_focus = (look_from - look_at).length();
-                let aperture = 2.0;
-
-                settings.camera = Camera::new(
-                    look_from,
-                    look_at,
-                    Vec3::new(0.0, 1.0, 0.0),
-                    fov, 
-                    aperture,
-                    dist_to_focus);
-                launch_render(&settings)?;
-            }
+            let fov = 30.0;
+            let t = 2.0 * PI *(i as Scalar) / (nb_frames as Scalar);
+            let look_from = look_around(look_at, 5.0, t);
+            let dist_to_focus = (look_from - look_at).length();
+            let aperture = 2.0;
+            Camera::new(look_from, look_at, vup, fov, aperture, dist_to_focus)
         }
         Anim::Tournette2 => {
-            for i in 0..nb_frames {
-                let mut settings: settings::Settings = Default::default();
-
-                let fov = 50.0;
-                let dist = 2.0;
-                let look_at = Point3::new(0.0, 0.0, -1.0);
-                let mut t = (i as Scalar) / (nb_frames as Scalar);
-                t = linear_step(t, -2.0 * PI *(1.0 / 6.0), 2.0 * PI *(1.0 / 6.0));
-
-                let look_from = look_at + 
-                    Point3::new(dist * Scalar::cos(t), 
-                                0.8, 
-                                dist * Scalar::sin(t) );
-
-                let dist_to_focus = (look_from - look_at).length();
-                let aperture = 0.3;
-
-                settings.camera = Camera::new(
-                    look_from,
-                    look_at,
-                    Vec3::new(0.0, 1.0, 0.0),
-                    fov, 
-                    aperture,
-                    dist_to_focus);
-                launch_render(&settings)?;
-            }  
+            let fov = 50.0;
+            let mut t = (i as Scalar) / (nb_frames as Scalar);
+            t = linear_step(t, -2.0 * PI *(1.0 / 6.0), 2.0 * PI *(1.0 / 6.0));
+            let look_from = look_around(look_at, 2.0, t);
+            let dist_to_focus = (look_from - look_at).length();
+            let aperture = 0.3;
+            Camera::new(look_from, look_at, vup, fov, aperture, dist_to_focus)
         }
         Anim::Fov => {
-            for i in 0..nb_frames {
-                let mut settings: settings::Settings = Default::default();
-
-                let fov = linear_step((i as Scalar) / ((nb_frames-1) as Scalar), 10.0, 90.0);
-                let dist = 5.0;
-                let t = 2.0 * PI * (1.0 / 8.0);
-
-                let look_at = Point3::new(0.0, 0.0, -1.0);
-
-                let look_from = look_at + 
-                    Point3::new(dist * Scalar::cos(t), 
-                                0.8, 
-                                dist * Scalar::sin(t) );
-                let dist_to_focus = (look_from - look_at).length();
-                let aperture = 2.0;
-                settings.camera = Camera::new(
-                    look_from,
-                    look_at,
-                    Vec3::new(0.0, 1.0, 0.0),
-                    fov, 
-                    aperture,
-                    dist_to_focus);
-                launch_render(&settings)?;
-            }
+            let fov = linear_step((i as Scalar) / ((nb_frames-1) as Scalar), 10.0, 90.0);
+            let t = 2.0 * PI * (1.0 / 8.0);
+            let look_from = look_around(look_at, 5.0, t);
+            let dist_to_focus = (look_from - look_at).length();
+            let aperture = 2.0;
+            Camera::new(look_from, look_at, vup, fov, aperture, dist_to_focus)
         }
         Anim::Aperture => {
-            for i in 0..nb_frames {
-                let mut settings: settings::Settings = Default::default();
-
-                let fov = 50.0;
-                let dist = 2.0;
-                let look_at = Point3::new(0.0, 0.0, -1.0);
-                let t = 2.0 * PI *(1.0 / 6.0);
-                let look_from = look_at + 
-                    Point3::new(dist * Scalar::cos(t), 
-                                0.8, 
-                                dist * Scalar::sin(t) );
-                let dist_to_focus = (look_from - look_at).length();
-
-                let t = (i as Scalar) / ((nb_frames-1) as Scalar);
-
-                let aperture = linear_step(t, 0.0, 1.0);
-                        
-                settings.camera = Camera::new(
-                    look_from,
-                    look_at,
-                    Vec3::new(0.0, 1.0, 0.0),
-                    fov, 
-                    aperture,
-                dist_to_focus);
-                    
-                launch_render(&settings)?; 
-            }
+            let fov = 50.0;
+            let look_from = look_around(look_at, 2.0, 2.0 * PI *(1.0 / 6.0));
+            let dist_to_focus = (look_from - look_at).length();
+            let t = (i as Scalar) / ((nb_frames-1) as Scalar);
+            let aperture = linear_step(t, 0.0, 1.0);
+            Camera::new(look_from, look_at, vup, fov, aperture, dist_to_focus)
         }
         Anim::DistFocus => {
-            for i in 0..nb_frames {
-                let mut settings: settings::Settings = Default::default();
+            let fov = 50.0;
+            let look_from = look_around(look_at, 2.0, 2.0 * PI *(1.0 / 6.0));
+            let aperture = 0.35;
+            let t = (i as Scalar) / ((nb_frames-1) as Scalar);
+            let dist_center = (look_from - look_at).length();
+            let dist_to_focus = linear_step(t, 0.8 * dist_center, 1.2*dist_center);
+            Camera::new(look_from, look_at, vup, fov, aperture, dist_to_focus)
+        }
+    }
+}
 
-                let fov = 50.0;
-                let dist = 2.0;
-                let look_at = Point3::new(0.0, 0.0, -1.0);
-                let t = 2.0 * PI *(1.0 / 6.0);
-                let look_from = look_at + 
-                    Point3::new(dist * Scalar::cos(t), 
-                                0.8, 
-                                dist * Scalar::sin(t) );
-                                
-                let aperture = 0.35;
-                let t = (i as Scalar) / ((nb_frames-1) as Scalar);
-                let dist_center = (look_from - look_at).length();
-                let dist_to_focus = linear_step(t, 0.8 * dist_center, 1.2*dist_center);
+fn load_scene(path: &str) -> std::io::Result<scene::Scene> {
+    scene::load_scene(path)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+fn run(cli: Cli) -> std::io::Result<()> {
 
-                        
-                settings.camera = Camera::new(
-                    look_from,
-                    look_at,
-                    Vec3::new(0.0, 1.0, 0.0),
-                    fov, 
-                    aperture,
-                dist_to_focus);
-                    
-                launch_render(&settings)?; 
+    match cli.command {
+        Command::Render(args) => {
+            let mut scene = load_scene(&args.scene)?;
+            args.apply(&mut scene.settings).unwrap_or_else(|e| e.exit());
+            let world = BvhNode::new(scene.world);
+            let render_scene = RenderScene { world: &world, lights: &scene.lights, material_ids: &scene.material_ids };
+            launch_render(&scene.settings, &render_scene)?;
+        }
+        Command::Animate { render: args, kind, frames } => {
+            let mut scene = load_scene(&args.scene)?;
+            args.apply(&mut scene.settings).unwrap_or_else(|e| e.exit());
+            let world = BvhNode::new(scene.world);
+            let render_scene = RenderScene { world: &world, lights: &scene.lights, material_ids: &scene.material_ids };
+            for i in 0..frames {
+                let mut settings = scene.settings.clone();
+                settings.camera = anim_camera(kind, i, frames);
+                settings.fit_camera();
+                println!("Frame {}/{}", i + 1, frames);
+                launch_render(&settings, &render_scene)?;
             }
         }
+        Command::Info { scene: path } => {
+            let scene = load_scene(&path)?;
+            println!("Scene {}", path);
+            println!("{} objects, {} lights", scene.world.len(), scene.lights.len());
+            scene.settings.dump();
+        }
     }
     
     Ok(())
 }
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -1,77 +1,126 @@
-use std::iter::Scan;
-
 use crate::color::{self, Color};
-use crate::noise::Perlin;
-use crate::{checkerboard, common, to_spherical};
+use crate::common::{self, SP};
+use crate::sampler::Sampler;
 use crate::hittable::HitRecord;
 use crate::ray::Ray;
+use crate::texture::Texture;
 use crate::vec3::{self, Scalar};
 
+// Kind of bounce a scattered ray comes from
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Lobe {
+    Diffuse,
+    // rough reflection, sampled with a pdf like the diffuse one
+    Glossy,
+    // mirror or fuzzy mirror reflection
+    Specular,
+    // refraction through the surface
+    Transmission,
+}
+
+// Outcome of the sampling of a material
+pub struct ScatterRecord {
+    // brdf * cosine / pdf, what the radiance carried by the scattered ray is multiplied by
+    pub attenuation: Color,
+    pub scattered: Ray,
+    pub lobe: Lobe,
+    // density of the scattered direction, per solid angle
+    pub pdf: Scalar,
+}
+
+impl ScatterRecord {
+    pub fn specular(attenuation: Color, scattered: Ray, lobe: Lobe) -> ScatterRecord {
+        ScatterRecord {
+            attenuation,
+            scattered,
+            lobe,
+            pdf: 0.0,
+        }
+    }
+
+    // specular samples come from a dirac distribution (or close to it) : no pdf, eval is meaningless
+    pub fn is_specular(&self) -> bool {
+        matches!(self.lobe, Lobe::Specular | Lobe::Transmission)
+    }
+}
 
 pub trait Material: Send + Sync {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool;
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord>;
+
+    // brdf times the cosine term, for a given scattered direction
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Color {
+        color::black()
+    }
+
+    // density with which scatter would have picked the scattered direction
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Scalar {
+        0.0
+    }
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        color::black()
+    }
+
+    // reflectance at normal incidence, for the albedo pass
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        color::black()
+    }
 }
 
 
 pub struct Lambertian {
-    albedo: Color,
-    checker: Option<Scalar>,
-
-    perlin: Perlin,
+    albedo: SP<dyn Texture>,
 }
 
 impl Lambertian {
-    pub fn new(albedo: Color, checker: Option<Scalar>) -> Lambertian {
+    pub fn new(albedo: SP<dyn Texture>) -> Lambertian {
         Lambertian{
             albedo,
-            checker,
-            perlin: Perlin::new(),
         }
     }
+
+    fn cosine(rec: &HitRecord, scattered: &Ray) -> Scalar {
+        Scalar::max(vec3::dot(rec.normal, vec3::unit_vector(scattered.direction())), 0.0)
+    }
 }
 
 impl Material for Lambertian {
-    fn scatter(
-        &self,
-        _r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-
-        *attenuation = self.albedo;
-        if self.checker.is_some() {
-            // if checkerboard(rec.uv.x(), self.checker.unwrap()) 
-            //     ^ checkerboard(rec.uv.y(), self.checker.unwrap()) 
-            // {
-            //     *attenuation = 0.5 * self.albedo;
-            // }
-            *attenuation = self.perlin.cell_noise(rec.p) * self.albedo;
-        }
-
-        let mut scatter_direction = rec.normal + vec3::random_unit_vector();
+    // cosine weighted sampling, the brdf * cosine / pdf ratio is the albedo
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        let mut scatter_direction = rec.normal + vec3::random_unit_vector(sampler);
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
-        *scattered = Ray::new(rec.p, scatter_direction);
+        let scattered = Ray::new(rec.p, scatter_direction);
+
+        Some(ScatterRecord {
+            attenuation: self.albedo(rec),
+            pdf: Self::cosine(rec, &scattered) / common::PI,
+            scattered,
+            lobe: Lobe::Diffuse,
+        })
+    }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        Self::cosine(rec, scattered) / common::PI * self.albedo(rec)
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Scalar {
+        Self::cosine(rec, scattered) / common::PI
+    }
 
-        true
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec)
     }
 }
 
 pub struct Metal {
-    albedo: Color,
+    albedo: SP<dyn Texture>,
     fuzz: Scalar
 }
 
 impl Metal {
-    pub fn new(albedo: Color, fuzz: Scalar) -> Metal {
+    pub fn new(albedo: SP<dyn Texture>, fuzz: Scalar) -> Metal {
         Metal{
             albedo,
             fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
@@ -80,34 +129,44 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        *attenuation = self.albedo;
-
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
         let mut reflected_direction = vec3::reflect(vec3::unit_vector(r_in.direction()), rec.normal);
-        reflected_direction = reflected_direction + self.fuzz * vec3::random_in_unit_sphere();
-        *scattered = Ray::new(rec.p, reflected_direction);
+        reflected_direction += self.fuzz * vec3::random_in_unit_sphere(sampler);
+        let scattered = Ray::new(rec.p, reflected_direction);
+
+        if vec3::dot(scattered.direction(), rec.normal) <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord::specular(self.albedo.value(rec), scattered, Lobe::Specular))
+    }
 
-        vec3::dot(scattered.direction(), rec.normal) > 0.0
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec)
     }
 }
 
 pub struct Dielectric {
     ir: Scalar,
+    // of the reflections off the outside of the surface
+    tint: SP<dyn Texture>,
+    // per unit of distance travelled inside the medium
+    absorption: Color,
 }
 
 impl Dielectric {
-    pub fn new(ir: Scalar) -> Dielectric {
+    pub fn new(ir: Scalar, tint: SP<dyn Texture>, absorption: Color) -> Dielectric {
         Dielectric{
             ir,
+            tint,
+            absorption,
         }
     }
 
+    // absorption coefficient letting through transmittance of the light over distance
+    pub fn absorption(transmittance: Color, distance: Scalar) -> Color {
+        color::map_channels(transmittance, |t| -Scalar::ln(t.clamp(1e-6, 1.0)) / distance.max(1e-6))
+    }
+
     fn reflectance(cosine:Scalar, ref_idx: Scalar) -> Scalar {
         // Schlick
         let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
@@ -117,13 +176,7 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
         let refraction_ratio = if rec.front_face {
             1.0 / self.ir
         } else {
@@ -137,18 +190,162 @@ impl Material for Dielectric {
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let direction = if cannot_refract
-            || Self::reflectance(cos_theta, refraction_ratio) > common::random_double()
+        let (direction, lobe) = if cannot_refract
+            || Self::reflectance(cos_theta, refraction_ratio) > sampler.get_1d()
         {
-            vec3::reflect(unit_direction, rec.normal)
+            (vec3::reflect(unit_direction, rec.normal), Lobe::Specular)
         }
         else {
-            vec3::refract(unit_direction, rec.normal, refraction_ratio)
+            (vec3::refract(unit_direction, rec.normal, refraction_ratio), Lobe::Transmission)
+        };
+
+        // Beer-Lambert, over the way from the previous hit, which was inside when hitting the back of the surface
+        // (objects inside the medium are not accounted for)
+        let mut attenuation = if rec.front_face {
+            color::white()
+        } else {
+            let distance = rec.t * r_in.direction().length();
+            color::map_channels(-distance * self.absorption, Scalar::exp)
         };
+        if rec.front_face && lobe == Lobe::Specular {
+            attenuation = attenuation * self.tint.value(rec);
+        }
+
+        Some(ScatterRecord::specular(attenuation, Ray::new(rec.p, direction), lobe))
+    }
 
-        *attenuation = color::white();
-        *scattered = Ray::new(rec.p, direction);
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.tint.value(rec)
+    }
+}
+
+// One of two materials, b being picked with the probability given by the amount texture (its luminance).
+// The pick is a hash of the hit point, the same for scatter, eval and pdf of a given hit.
+pub struct MixMaterial {
+    a: SP<dyn Material>,
+    b: SP<dyn Material>,
+    amount: SP<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new(a: SP<dyn Material>, b: SP<dyn Material>, amount: SP<dyn Texture>) -> MixMaterial {
+        MixMaterial { a, b, amount }
+    }
+
+    fn choose(&self, rec: &HitRecord) -> &SP<dyn Material> {
+        let amount = color::luminance(self.amount.value(rec));
+        if amount <= 0.0 {
+            return &self.a;
+        }
+        if amount >= 1.0 {
+            return &self.b;
+        }
+        let hash = rec.p.e.iter().fold(0, |h, c| common::mix(h ^ c.to_bits()));
+        let u = (hash >> 11) as Scalar / (1u64 << 53) as Scalar;
+        if u < amount { &self.b } else { &self.a }
+    }
+}
 
-        true
+impl Material for MixMaterial {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        self.choose(rec).scatter(r_in, rec, sampler)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.choose(rec).eval(r_in, rec, scattered)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Scalar {
+        self.choose(rec).pdf(r_in, rec, scattered)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.choose(rec).emitted(rec)
+    }
+
+    // blended, the albedo pass is not noisy
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        let amount = color::luminance(self.amount.value(rec)).clamp(0.0, 1.0);
+        (1.0 - amount) * self.a.albedo(rec) + amount * self.b.albedo(rec)
+    }
+}
+
+pub struct DiffuseLight {
+    emit: SP<dyn Texture>,
+    intensity: Scalar,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: SP<dyn Texture>, intensity: Scalar) -> DiffuseLight {
+        DiffuseLight{
+            emit,
+            intensity,
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.intensity * self.emit.value(rec)
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        color::white()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+    use crate::hittable::Hittable;
+    use crate::hittable_list::HittableList;
+    use crate::quad::Quad;
+    use crate::sampler::SamplerKind;
+    use crate::texture;
+    use crate::vec3::{Point3, Vec3};
+
+    // the cube [-1, 1]^3, the quads facing out
+    fn quad_box(mat: SP<dyn Material>) -> HittableList {
+        let mut list = HittableList::new();
+        let sides = [
+            (Point3::new(-1.0, -1.0, 1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0)),
+            (Point3::new(-1.0, -1.0, -1.0), Vec3::new(0.0, 2.0, 0.0), Vec3::new(2.0, 0.0, 0.0)),
+            (Point3::new(1.0, -1.0, -1.0), Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 0.0, 2.0)),
+            (Point3::new(-1.0, -1.0, -1.0), Vec3::new(0.0, 0.0, 2.0), Vec3::new(0.0, 2.0, 0.0)),
+            (Point3::new(-1.0, 1.0, -1.0), Vec3::new(0.0, 0.0, 2.0), Vec3::new(2.0, 0.0, 0.0)),
+            (Point3::new(-1.0, -1.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0)),
+        ];
+        for (origin, u, v) in sides {
+            list.add(Box::new(Quad::new(origin, u, v, mat.clone())));
+        }
+        list
+    }
+
+    #[test]
+    fn absorption_inside_a_quad_box() {
+        // an index of 1 lets the ray straight through, without reflection
+        let absorption = Color::new(0.5, 1.0, 2.0);
+        let glass = quad_box(SP::new(Dielectric::new(1.0, texture::solid(color::white()), absorption)));
+        let mut sampler = SamplerKind::Independent.sampler(0, 1, 0, 0, 0);
+
+        // 9 units of air before the box, 2 inside it
+        let mut ray = Ray::new(Point3::new(0.1, 0.2, 10.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut throughput = color::white();
+        for front_face in [true, false] {
+            let mut rec = HitRecord::new();
+            assert!(glass.hit(&ray, 0.001, common::INFINITY, &mut rec));
+            assert_eq!(rec.front_face, front_face);
+            let scatter = rec.mat.clone().unwrap().scatter(&ray, &rec, sampler.as_mut()).unwrap();
+            assert!(scatter.lobe == Lobe::Transmission);
+            throughput = throughput * scatter.attenuation;
+            ray = scatter.scattered;
+        }
+        let expected = color::map_channels(-2.0 * absorption, Scalar::exp);
+        assert!((throughput - expected).length() < 1e-9);
     }
 }
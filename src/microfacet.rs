@@ -0,0 +1,373 @@
+use clap::ValueEnum;
+
+use crate::color::{self, Color};
+use crate::common::{self, SP};
+use crate::hittable::HitRecord;
+use crate::material::{Lobe, Material, ScatterRecord};
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::texture::Texture;
+use crate::vec3::{self, Scalar, Vec3};
+
+// GGX (Trowbridge-Reitz) distribution of the microfacet normals, with the Smith height correlated masking.
+// The directions are in the shading frame, z along the normal.
+pub struct Ggx {
+    alpha: Scalar,
+}
+
+impl Ggx {
+    // perceptually linear roughness, squared into the width of the distribution
+    pub fn new(roughness: Scalar) -> Ggx {
+        Ggx { alpha: (roughness * roughness).max(1e-3) }
+    }
+
+    pub fn d(&self, h: Vec3) -> Scalar {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = h.z() * h.z() * (a2 - 1.0) + 1.0;
+        a2 / (common::PI * t * t)
+    }
+
+    fn lambda(&self, w: Vec3) -> Scalar {
+        let cos2 = w.z() * w.z();
+        if cos2 <= 0.0 {
+            return Scalar::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * (Scalar::sqrt(1.0 + self.alpha * self.alpha * tan2) - 1.0)
+    }
+
+    pub fn g1(&self, w: Vec3) -> Scalar {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> Scalar {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // normal of the microfacets seen from wo, distributed as D * G1 * cos (Heitz 2018)
+    pub fn sample_visible_normal(&self, wo: Vec3, u1: Scalar, u2: Scalar) -> Vec3 {
+        let vh = vec3::unit_vector(Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()));
+        let length_squared = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if length_squared > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / length_squared.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vec3::cross(vh, t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * common::PI * u2;
+        let p1 = r * Scalar::cos(phi);
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * Scalar::sqrt((1.0 - p1 * p1).max(0.0)) + s * r * Scalar::sin(phi);
+        let nh = p1 * t1 + p2 * t2 + Scalar::sqrt((1.0 - p1 * p1 - p2 * p2).max(0.0)) * vh;
+        vec3::unit_vector(Vec3::new(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(1e-6)))
+    }
+
+    // density of the reflected direction when the normal comes from sample_visible_normal
+    pub fn pdf_reflected(&self, wo: Vec3, h: Vec3) -> Scalar {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * self.d(h) / (4.0 * wo.z())
+    }
+}
+
+pub fn fresnel_schlick(f0: Color, cos_theta: Scalar) -> Color {
+    f0 + Scalar::powi(1.0 - cos_theta.clamp(0.0, 1.0), 5) * (color::white() - f0)
+}
+
+// unpolarized reflectance of a conductor of complex index eta + ik
+fn fresnel_conductor_channel(cos_theta: Scalar, eta: Scalar, k: Scalar) -> Scalar {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2b2 = Scalar::sqrt(t0 * t0 + 4.0 * eta * eta * k * k);
+    let a = Scalar::sqrt((0.5 * (a2b2 + t0)).max(0.0));
+    let t1 = a2b2 + cos2;
+    let t2 = 2.0 * a * cos_theta;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+}
+
+// Measured complex refractive indices, at 650, 550 and 450 nm
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Conductor {
+    Gold,
+    Copper,
+    Aluminum,
+    Silver,
+}
+
+impl Conductor {
+    fn eta_k(&self) -> (Vec3, Vec3) {
+        match self {
+            Conductor::Gold => (Vec3::new(0.143, 0.374, 1.442), Vec3::new(3.983, 2.385, 1.603)),
+            Conductor::Copper => (Vec3::new(0.200, 0.924, 1.102), Vec3::new(3.912, 2.452, 2.142)),
+            Conductor::Aluminum => (Vec3::new(1.657, 0.880, 0.521), Vec3::new(9.224, 6.269, 4.837)),
+            Conductor::Silver => (Vec3::new(0.155, 0.117, 0.138), Vec3::new(4.828, 3.122, 2.147)),
+        }
+    }
+
+    pub fn fresnel(&self, cos_theta: Scalar) -> Color {
+        let (eta, k) = self.eta_k();
+        Color::new(
+            fresnel_conductor_channel(cos_theta, eta.x(), k.x()),
+            fresnel_conductor_channel(cos_theta, eta.y(), k.y()),
+            fresnel_conductor_channel(cos_theta, eta.z(), k.z()))
+    }
+}
+
+// basis around the normal, and the direction towards the viewer in it
+fn shading_frame(rec: &HitRecord, r_in: &Ray) -> (Onb, Vec3) {
+    let onb = Onb::new(rec.normal);
+    let wo = onb.to_local(-vec3::unit_vector(r_in.direction()));
+    (onb, wo)
+}
+
+// cosine weighted, in the shading frame
+fn cosine_direction(u1: Scalar, u2: Scalar) -> Vec3 {
+    let r = u1.sqrt();
+    let phi = 2.0 * common::PI * u2;
+    Vec3::new(r * Scalar::cos(phi), r * Scalar::sin(phi), Scalar::sqrt((1.0 - u1).max(0.0)))
+}
+
+// A GGX specular lobe over a diffuse one. The materials provide the brdf and the energy each lobe
+// reflects, the lobe of a bounce is picked in proportion to it.
+trait GlossyOverDiffuse {
+    fn ggx(&self) -> &Ggx;
+    // color of the diffuse base at the hit
+    fn base(&self, rec: &HitRecord) -> Color;
+    // of the specular and diffuse lobes, seen from wo
+    fn lobe_energies(&self, base: Color, wo: Vec3) -> (Scalar, Scalar);
+    // brdf * cosine, both directions above the surface
+    fn eval_lobes(&self, base: Color, wo: Vec3, wi: Vec3) -> Color;
+
+    fn specular_probability(&self, base: Color, wo: Vec3) -> Scalar {
+        let (specular, diffuse) = self.lobe_energies(base, wo);
+        if specular + diffuse <= 0.0 {
+            return 0.5;
+        }
+        (specular / (specular + diffuse)).clamp(0.1, 1.0)
+    }
+
+    fn eval_local(&self, base: Color, wo: Vec3, wi: Vec3) -> Color {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return color::black();
+        }
+        self.eval_lobes(base, wo, wi)
+    }
+
+    fn pdf_local(&self, base: Color, wo: Vec3, wi: Vec3) -> Scalar {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let h = vec3::unit_vector(wo + wi);
+        let p = self.specular_probability(base, wo);
+        p * self.ggx().pdf_reflected(wo, h) + (1.0 - p) * wi.z() / common::PI
+    }
+
+    fn scatter_lobes(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        let (onb, wo) = shading_frame(rec, r_in);
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let base = self.base(rec);
+        let choice = sampler.get_1d();
+        let (u1, u2) = sampler.get_2d();
+        let (wi, lobe) = if choice < self.specular_probability(base, wo) {
+            let h = self.ggx().sample_visible_normal(wo, u1, u2);
+            (vec3::reflect(-wo, h), Lobe::Glossy)
+        } else {
+            (cosine_direction(u1, u2), Lobe::Diffuse)
+        };
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        let pdf = self.pdf_local(base, wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            attenuation: self.eval_local(base, wo, wi) / pdf,
+            scattered: Ray::new(rec.p, onb.local(wi)),
+            lobe,
+            pdf,
+        })
+    }
+
+    fn eval_lobes_world(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let (onb, wo) = shading_frame(rec, r_in);
+        let wi = onb.to_local(vec3::unit_vector(scattered.direction()));
+        self.eval_local(self.base(rec), wo, wi)
+    }
+
+    fn pdf_lobes_world(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Scalar {
+        let (onb, wo) = shading_frame(rec, r_in);
+        let wi = onb.to_local(vec3::unit_vector(scattered.direction()));
+        self.pdf_local(self.base(rec), wo, wi)
+    }
+}
+
+// Metallic / roughness material : a Lambertian base under a GGX specular layer.
+// Metals have no base, their reflectance is the base color (Schlick) or the one of a measured conductor.
+pub struct Principled {
+    base_color: SP<dyn Texture>,
+    metallic: Scalar,
+    ggx: Ggx,
+    // specular reflectance at normal incidence of the dielectric part
+    f0: Scalar,
+    conductor: Option<Conductor>,
+}
+
+impl Principled {
+    pub fn new(base_color: SP<dyn Texture>, metallic: Scalar, roughness: Scalar, ior: Scalar, conductor: Option<Conductor>) -> Principled {
+        let f0 = ((ior - 1.0) / (ior + 1.0)).powi(2);
+        Principled {
+            base_color,
+            metallic: if conductor.is_some() { 1.0 } else { metallic.clamp(0.0, 1.0) },
+            ggx: Ggx::new(roughness.clamp(0.0, 1.0)),
+            f0,
+            conductor,
+        }
+    }
+
+    fn fresnel(&self, base: Color, cos_theta: Scalar) -> Color {
+        match self.conductor {
+            Some(conductor) => base * conductor.fresnel(cos_theta),
+            None => {
+                let f0 = (1.0 - self.metallic) * Color::uni(self.f0) + self.metallic * base;
+                fresnel_schlick(f0, cos_theta)
+            }
+        }
+    }
+
+    fn diffuse_weight(&self, wo: Vec3) -> Scalar {
+        // what the specular layer lets through at the viewing angle
+        (1.0 - self.metallic) * (1.0 - fresnel_schlick(Color::uni(self.f0), wo.z()).x())
+    }
+}
+
+impl GlossyOverDiffuse for Principled {
+    fn ggx(&self) -> &Ggx {
+        &self.ggx
+    }
+
+    fn base(&self, rec: &HitRecord) -> Color {
+        self.base_color.value(rec)
+    }
+
+    fn lobe_energies(&self, base: Color, wo: Vec3) -> (Scalar, Scalar) {
+        (color::luminance(self.fresnel(base, wo.z())), self.diffuse_weight(wo) * color::luminance(base))
+    }
+
+    fn eval_lobes(&self, base: Color, wo: Vec3, wi: Vec3) -> Color {
+        let h = vec3::unit_vector(wo + wi);
+        let specular = self.ggx.d(h) * self.ggx.g2(wo, wi) / (4.0 * wo.z()) * self.fresnel(base, vec3::dot(wo, h));
+        let diffuse = self.diffuse_weight(wo) * wi.z() / common::PI * base;
+        specular + diffuse
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        self.scatter_lobes(r_in, rec, sampler)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.eval_lobes_world(r_in, rec, scattered)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Scalar {
+        self.pdf_lobes_world(r_in, rec, scattered)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        let base = self.base_color.value(rec);
+        match self.conductor {
+            Some(conductor) => base * conductor.fresnel(1.0),
+            None => base,
+        }
+    }
+}
+
+// Hemispherical reflectance of a smooth dielectric boundary seen from the denser side (Egan-Hilgeman fit)
+fn internal_diffuse_reflectance(ior: Scalar) -> Scalar {
+    -1.440 / (ior * ior) + 0.710 / ior + 0.668 + 0.0636 * ior
+}
+
+// Clear dielectric coat over a Lambertian base : glossy plastic, varnish, lacquered wood.
+// The light entering the coat bounces between the base and the underside of the coat before leaving.
+pub struct CoatedDiffuse {
+    albedo: SP<dyn Texture>,
+    ior: Scalar,
+    ggx: Ggx,
+    f0: Scalar,
+    internal_reflectance: Scalar,
+}
+
+impl CoatedDiffuse {
+    pub fn new(albedo: SP<dyn Texture>, ior: Scalar, roughness: Scalar) -> CoatedDiffuse {
+        CoatedDiffuse {
+            albedo,
+            ior,
+            ggx: Ggx::new(roughness.clamp(0.0, 1.0)),
+            f0: ((ior - 1.0) / (ior + 1.0)).powi(2),
+            internal_reflectance: internal_diffuse_reflectance(ior).clamp(0.0, 1.0),
+        }
+    }
+
+    fn coat_fresnel(&self, cos_theta: Scalar) -> Scalar {
+        fresnel_schlick(Color::uni(self.f0), cos_theta).x()
+    }
+}
+
+impl GlossyOverDiffuse for CoatedDiffuse {
+    fn ggx(&self) -> &Ggx {
+        &self.ggx
+    }
+
+    fn base(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec)
+    }
+
+    fn lobe_energies(&self, albedo: Color, wo: Vec3) -> (Scalar, Scalar) {
+        let specular = self.coat_fresnel(wo.z());
+        (specular, (1.0 - specular) * color::luminance(albedo))
+    }
+
+    fn eval_lobes(&self, albedo: Color, wo: Vec3, wi: Vec3) -> Color {
+        let h = vec3::unit_vector(wo + wi);
+        let specular = self.ggx.d(h) * self.ggx.g2(wo, wi) / (4.0 * wo.z()) * self.coat_fresnel(vec3::dot(wo, h));
+        // transmitted in and out of the coat, the base seen through the internal reflections
+        let transmitted = (1.0 - self.coat_fresnel(wo.z())) * (1.0 - self.coat_fresnel(wi.z()));
+        let bounces = color::white() - self.internal_reflectance * albedo;
+        let diffuse = transmitted * wi.z() / (common::PI * self.ior * self.ior) * albedo / bounces;
+        Color::uni(specular) + diffuse
+    }
+}
+
+impl Material for CoatedDiffuse {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        self.scatter_lobes(r_in, rec, sampler)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.eval_lobes_world(r_in, rec, scattered)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Scalar {
+        self.pdf_lobes_world(r_in, rec, scattered)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec)
+    }
+}
@@ -1,51 +1,193 @@
-use crate::{random_double, random_int, Point3, Scalar};
+use clap::ValueEnum;
 
-const POINT_COUNT: u32 = 256;
+use crate::common::{random_double, random_int, Rng};
+use crate::vec3::{self, Point3, Scalar, Vec3};
 
+const POINT_COUNT: u32 = 256;
 
+// Lattice noises over the integer grid, the permutations hash the cells
 pub struct Perlin {
-    pub random_floats: Vec<Scalar>,
-
-    pub perm_x: Vec<u32>,
-    pub perm_y: Vec<u32>,
-    pub perm_z: Vec<u32>,
+    random_floats: Vec<Scalar>,
+    // unit gradients of the lattice points
+    gradients: Vec<Vec3>,
+    // one point in [0, 1)^3 per cell, for the cellular noise
+    feature_points: Vec<Vec3>,
 
+    perm_x: Vec<u32>,
+    perm_y: Vec<u32>,
+    perm_z: Vec<u32>,
 }
 
-fn permute(ints: &mut Vec<u32>, count: u32) {
+fn permute(rng: &mut Rng, ints: &mut [u32], count: u32) {
     for i in 0..count {
-        let target = random_int(0..count);
+        let target = random_int(rng, 0..count);
         ints.swap(target as usize, i as usize);
     }
 }
 
-fn perlin_generate_perm() -> Vec<u32> {
-    let mut ints: Vec<u32> = (0..POINT_COUNT)
-    .map(|i| i) // Convert each index to f64
-    .collect();  
+fn perlin_generate_perm(rng: &mut Rng) -> Vec<u32> {
+    let mut ints: Vec<u32> = (0..POINT_COUNT).collect();
 
-    permute(&mut ints, POINT_COUNT);
+    permute(rng, &mut ints, POINT_COUNT);
     ints
 }
 
+fn random_gradient(rng: &mut Rng) -> Vec3 {
+    loop {
+        let v = Vec3::new(
+            2.0 * random_double(rng) - 1.0,
+            2.0 * random_double(rng) - 1.0,
+            2.0 * random_double(rng) - 1.0,
+        );
+        let length_squared = v.length_squared();
+        if length_squared > 1e-6 && length_squared <= 1.0 {
+            return v / length_squared.sqrt();
+        }
+    }
+}
+
+// 6t^5 - 15t^4 + 10t^3, zero first and second derivatives at the lattice points
+fn fade(t: Scalar) -> Scalar {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+// Octaves of a noise summed, each one lacunarity times finer and gain times weaker than the previous
+#[derive(Copy, Clone)]
+pub struct Fractal {
+    pub octaves: u32,
+    pub lacunarity: Scalar,
+    pub gain: Scalar,
+}
+
+impl Default for Fractal {
+    fn default() -> Self {
+        Fractal { octaves: 6, lacunarity: 2.0, gain: 0.5 }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum NoiseKind {
+    // one random value per unit cube
+    Cell,
+    // gradient noise, smooth
+    Perlin,
+    // fractal sum of gradient noise
+    Fbm,
+    // fractal sum of the absolute gradient noise, creased
+    Turbulence,
+    // distance to the nearest feature point
+    Worley,
+}
+
 impl Perlin {
-    pub fn new() -> Perlin {
+    pub fn new(rng: &mut Rng) -> Perlin {
         let random_floats: Vec<Scalar> = (0..POINT_COUNT)
-        .map(|_| random_double()) // Convert each index to f64
-        .collect();  
-        Perlin {  
+        .map(|_| random_double(rng)) // Convert each index to f64
+        .collect();
+        let gradients = (0..POINT_COUNT).map(|_| random_gradient(rng)).collect();
+        let feature_points = (0..POINT_COUNT)
+            .map(|_| Vec3::new(random_double(rng), random_double(rng), random_double(rng)))
+            .collect();
+        Perlin {
             random_floats,
-            perm_x: perlin_generate_perm(),
-            perm_y: perlin_generate_perm(),
-            perm_z: perlin_generate_perm(),
+            gradients,
+            feature_points,
+            perm_x: perlin_generate_perm(rng),
+            perm_y: perlin_generate_perm(rng),
+            perm_z: perlin_generate_perm(rng),
         }
     }
 
+    fn hash(&self, i: i64, j: i64, k: i64) -> usize {
+        let mask = (POINT_COUNT - 1) as i64;
+        (self.perm_x[(i & mask) as usize] ^ self.perm_y[(j & mask) as usize] ^ self.perm_z[(k & mask) as usize]) as usize
+    }
+
+    // in [0, 1)
     pub fn cell_noise(&self, p: Point3) -> Scalar {
-        let i = (((4.0 * p.x())).rem_euclid(POINT_COUNT as Scalar) as u32) & (POINT_COUNT-1);
-        let j = (((4.0 * p.y())).rem_euclid(POINT_COUNT as Scalar) as u32) & (POINT_COUNT-1);
-        let k = (((4.0 * p.z())).rem_euclid(POINT_COUNT as Scalar) as u32) & (POINT_COUNT-1);
+        self.random_floats[self.hash(p.x().floor() as i64, p.y().floor() as i64, p.z().floor() as i64)]
+    }
+
+    // gradient noise, in [-1, 1], zero on the lattice points
+    pub fn noise(&self, p: Point3) -> Scalar {
+        let (i, j, k) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (u, v, w) = (p.x() - i, p.y() - j, p.z() - k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.hash(i + di, j + dj, k + dk)];
+                    let offset = Vec3::new(u - di as Scalar, v - dj as Scalar, w - dk as Scalar);
+                    let weight = (if di == 1 { fade(u) } else { 1.0 - fade(u) })
+                        * (if dj == 1 { fade(v) } else { 1.0 - fade(v) })
+                        * (if dk == 1 { fade(w) } else { 1.0 - fade(w) });
+                    accum += weight * vec3::dot(gradient, offset);
+                }
+            }
+        }
+        // the gradient noise of unit gradients stays within sqrt(3)/2
+        (accum * 2.0 / Scalar::sqrt(3.0)).clamp(-1.0, 1.0)
+    }
 
-        self.random_floats[ (self.perm_x[i as usize] ^ self.perm_x[j as usize] ^ self.perm_x[k as usize]) as usize ]  
+    // fractional Brownian motion, in [-1, 1]
+    pub fn fbm(&self, p: Point3, fractal: &Fractal) -> Scalar {
+        self.fractal_sum(p, fractal, |n| n)
     }
-}
\ No newline at end of file
+
+    // in [0, 1]
+    pub fn turbulence(&self, p: Point3, fractal: &Fractal) -> Scalar {
+        self.fractal_sum(p, fractal, Scalar::abs)
+    }
+
+    // normalized by the sum of the amplitudes
+    fn fractal_sum(&self, p: Point3, fractal: &Fractal, f: impl Fn(Scalar) -> Scalar) -> Scalar {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        for _ in 0..fractal.octaves.max(1) {
+            sum += amplitude * f(self.noise(frequency * p));
+            total += amplitude;
+            amplitude *= fractal.gain;
+            frequency *= fractal.lacunarity;
+        }
+        if total > 0.0 { sum / total } else { 0.0 }
+    }
+
+    // distances to the nearest and second nearest feature points
+    pub fn worley(&self, p: Point3) -> (Scalar, Scalar) {
+        let (i, j, k) = (p.x().floor() as i64, p.y().floor() as i64, p.z().floor() as i64);
+        let mut f1 = Scalar::INFINITY;
+        let mut f2 = Scalar::INFINITY;
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let (ci, cj, ck) = (i + di, j + dj, k + dk);
+                    let feature = Vec3::new(ci as Scalar, cj as Scalar, ck as Scalar)
+                        + self.feature_points[self.hash(ci, cj, ck)];
+                    let d = (feature - p).length();
+                    if d < f1 {
+                        f2 = f1;
+                        f1 = d;
+                    } else if d < f2 {
+                        f2 = d;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+
+    // in [0, 1], to modulate a color
+    pub fn value(&self, kind: NoiseKind, p: Point3, fractal: &Fractal) -> Scalar {
+        match kind {
+            NoiseKind::Cell => self.cell_noise(p),
+            NoiseKind::Perlin => 0.5 * (1.0 + self.noise(p)),
+            NoiseKind::Fbm => 0.5 * (1.0 + self.fbm(p, fractal)),
+            NoiseKind::Turbulence => self.turbulence(p, fractal),
+            NoiseKind::Worley => self.worley(p).0.min(1.0),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::color::{self, Color};
+use crate::common::SP;
+use crate::material::*;
+use crate::scene::SceneError;
+use crate::texture::{self, ImageTexture, Texture, WrapMode};
+use crate::triangle::{MeshData, MeshVertex, TriangleMesh};
+use crate::vec3::{Point3, Scalar, Vec3};
+
+// Wavefront OBJ loader : positions, normals, texture coordinates and polygonal faces
+// `usemtl` names are looked up in the scene materials first, then in the mtllib files,
+// faces without material get the default one
+
+type ObjResult<T> = Result<T, SceneError>;
+
+fn parse_floats(line: usize, tokens: &[&str], min: usize) -> ObjResult<Vec<Scalar>> {
+    let values: Vec<Scalar> = tokens.iter()
+        .map(|t| t.parse::<Scalar>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| SceneError::new(line, format!("malformed numbers '{}'", tokens.join(" "))))?;
+    if values.len() < min {
+        return Err(SceneError::new(line, format!("expected {} numbers, found {}", min, values.len())));
+    }
+    Ok(values)
+}
+
+fn parse_vec3(line: usize, tokens: &[&str]) -> ObjResult<Vec3> {
+    let v = parse_floats(line, tokens, 3)?;
+    Ok(Vec3::new(v[0], v[1], v[2]))
+}
+
+// 1 based, negative indices are relative to the end of the buffer
+fn parse_index(line: usize, token: &str, len: usize) -> ObjResult<usize> {
+    let index = token.parse::<i64>()
+        .map_err(|_| SceneError::new(line, format!("malformed index '{}'", token)))?;
+    let resolved = if index < 0 { len as i64 + index } else { index - 1 };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(SceneError::new(line, format!("index {} out of range", index)));
+    }
+    Ok(resolved as usize)
+}
+
+fn parse_face_vertex(line: usize, token: &str, data: &MeshData) -> ObjResult<MeshVertex> {
+    let mut parts = token.split('/');
+    let position = parse_index(line, parts.next().unwrap_or(""), data.positions.len())?;
+    let texcoord = match parts.next() {
+        Some("") | None => None,
+        Some(t) => Some(parse_index(line, t, data.texcoords.len())?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(n) => Some(parse_index(line, n, data.normals.len())?),
+    };
+    Ok(MeshVertex { position, normal, texcoord })
+}
+
+#[derive(Default)]
+struct MtlEntry {
+    kd: Option<Color>,
+    // diffuse color image, relative to the mtl file, and the line it is given on
+    map_kd: Option<(usize, String)>,
+    ks: Option<Color>,
+    // transmission filter, taken as the transmittance over a unit of distance
+    tf: Option<Color>,
+    ke: Option<Color>,
+    ni: Option<Scalar>,
+    ns: Option<Scalar>,
+    dissolve: Option<Scalar>,
+    illum: Option<u32>,
+}
+
+impl MtlEntry {
+    fn to_material(&self, dir: &Path) -> ObjResult<SP<dyn Material>> {
+        let black = Color::default();
+        if let Some(ke) = self.ke.filter(|ke| !ke.near_zero()) {
+            return Ok(SP::new(DiffuseLight::new(texture::solid(ke), 1.0)));
+        }
+        let transparent = self.dissolve.is_some_and(|d| d < 1.0)
+            || matches!(self.illum, Some(4) | Some(6) | Some(7) | Some(9));
+        if transparent {
+            let absorption = Dielectric::absorption(self.tf.unwrap_or(color::white()), 1.0);
+            return Ok(SP::new(Dielectric::new(self.ni.unwrap_or(1.5), texture::solid(color::white()), absorption)));
+        }
+        if self.illum == Some(3) {
+            // phong exponent to a fuzz amount
+            let fuzz = Scalar::sqrt(2.0 / (self.ns.unwrap_or(1000.0) + 2.0));
+            return Ok(SP::new(Metal::new(texture::solid(self.ks.unwrap_or(black)), fuzz)));
+        }
+        let albedo: SP<dyn Texture> = match &self.map_kd {
+            Some((line, file)) => SP::new(ImageTexture::load(&dir.join(file), WrapMode::Repeat)
+                .map_err(|e| SceneError::new(*line, e.to_string()))?),
+            None => texture::solid(self.kd.unwrap_or(Color::uni(0.8))),
+        };
+        Ok(SP::new(Lambertian::new(albedo)))
+    }
+}
+
+// the errors name the mtl file, the obj one does not replace it
+fn load_mtl(path: &Path, materials: &mut HashMap<String, SP<dyn Material>>) -> ObjResult<()> {
+    let with_file = |mut e: SceneError| {
+        e.file = Some(path.display().to_string());
+        e
+    };
+    let source = fs::read_to_string(path)
+        .map_err(|e| with_file(SceneError::new(0, format!("cannot read material library: {}", e))))?;
+    parse_mtl(&source, path.parent().unwrap_or(Path::new(".")), materials).map_err(with_file)
+}
+
+// image files are relative to dir
+fn parse_mtl(source: &str, dir: &Path, materials: &mut HashMap<String, SP<dyn Material>>) -> ObjResult<()> {
+    let mut entries: Vec<(String, MtlEntry)> = Vec::new();
+    for (index, raw_line) in source.lines().enumerate() {
+        let line = index + 1;
+        let tokens: Vec<&str> = raw_line.split_whitespace().collect();
+        if tokens.is_empty() || tokens[0].starts_with('#') {
+            continue;
+        }
+        if tokens[0] == "newmtl" {
+            entries.push((tokens[1..].join(" "), MtlEntry::default()));
+            continue;
+        }
+        let Some((_, entry)) = entries.last_mut() else {
+            continue;
+        };
+        let args = &tokens[1..];
+        match tokens[0] {
+            "Kd" => entry.kd = Some(parse_vec3(line, args)?),
+            // the options before the file name are ignored
+            "map_Kd" => entry.map_kd = args.last().map(|file| (line, file.to_string())),
+            "Ks" => entry.ks = Some(parse_vec3(line, args)?),
+            // the xyz and spectral forms are not supported
+            "Tf" => entry.tf = parse_vec3(line, args).ok(),
+            "Ke" => entry.ke = Some(parse_vec3(line, args)?),
+            "Ni" => entry.ni = Some(parse_floats(line, args, 1)?[0]),
+            "Ns" => entry.ns = Some(parse_floats(line, args, 1)?[0]),
+            "d" => entry.dissolve = Some(parse_floats(line, args, 1)?[0]),
+            "Tr" => entry.dissolve = Some(1.0 - parse_floats(line, args, 1)?[0]),
+            "illum" => entry.illum = Some(parse_floats(line, args, 1)?[0] as u32),
+            _ => {}
+        }
+    }
+
+    for (name, entry) in entries {
+        if let Entry::Vacant(slot) = materials.entry(name) {
+            slot.insert(entry.to_material(dir)?);
+        }
+    }
+    Ok(())
+}
+
+pub fn load_obj(path: &Path,
+                scene_materials: &HashMap<String, SP<dyn Material>>,
+                default_material: SP<dyn Material>,
+                scale: Scalar,
+                translate: Vec3) -> ObjResult<TriangleMesh> {
+    let with_file = |mut e: SceneError| {
+        if e.file.is_none() {
+            e.file = Some(path.display().to_string());
+        }
+        e
+    };
+    let source = fs::read_to_string(path)
+        .map_err(|e| with_file(SceneError::new(0, format!("cannot read mesh: {}", e))))?;
+
+    let mut data = MeshData::default();
+    let mut faces = Vec::new();
+    let mut mtl_materials: HashMap<String, SP<dyn Material>> = HashMap::new();
+    let mut current_material = default_material.clone();
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let line = index + 1;
+        let content = match raw_line.find('#') {
+            Some(pos) => &raw_line[..pos],
+            None => raw_line,
+        };
+        let tokens: Vec<&str> = content.split_whitespace().collect();
+        if tokens.is_empty() {
+            continue;
+        }
+        let args = &tokens[1..];
+
+        match tokens[0] {
+            "v" => {
+                let p: Point3 = parse_vec3(line, args).map_err(with_file)?;
+                data.positions.push(scale * p + translate);
+            }
+            "vn" => data.normals.push(parse_vec3(line, args).map_err(with_file)?),
+            "vt" => {
+                let t = parse_floats(line, args, 1).map_err(with_file)?;
+                data.texcoords.push(Vec3::new(t[0], *t.get(1).unwrap_or(&0.0), 0.0));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(with_file(SceneError::new(line, "a face needs at least 3 vertices".to_string())));
+                }
+                let vertices: Vec<MeshVertex> = args.iter()
+                    .map(|t| parse_face_vertex(line, t, &data))
+                    .collect::<Result<_, _>>()
+                    .map_err(with_file)?;
+                // fan triangulation of convex polygons
+                for i in 1..vertices.len() - 1 {
+                    faces.push(([vertices[0], vertices[i], vertices[i + 1]], current_material.clone()));
+                }
+            }
+            "mtllib" => {
+                let dir = path.parent().unwrap_or(Path::new(""));
+                for file in args {
+                    load_mtl(&dir.join(file), &mut mtl_materials).map_err(with_file)?;
+                }
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                current_material = scene_materials.get(&name)
+                    .or_else(|| mtl_materials.get(&name))
+                    .cloned()
+                    .ok_or_else(|| with_file(SceneError::new(line, format!("unknown material '{}'", name))))?;
+            }
+            // groups, objects and smoothing groups don't matter here
+            _ => {}
+        }
+    }
+
+    if faces.is_empty() {
+        return Err(with_file(SceneError::new(0, "mesh has no faces".to_string())));
+    }
+    Ok(TriangleMesh::new(data, faces))
+}
@@ -0,0 +1,27 @@
+use crate::vec3::{self, Vec3};
+
+// Orthonormal basis, w being the given direction
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(n: Vec3) -> Onb {
+        let w = vec3::unit_vector(n);
+        let a = if w.x().abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let v = vec3::unit_vector(vec3::cross(w, a));
+        let u = vec3::cross(w, v);
+        Onb { u, v, w }
+    }
+
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+
+    // inverse of local
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(vec3::dot(a, self.u), vec3::dot(a, self.v), vec3::dot(a, self.w))
+    }
+}
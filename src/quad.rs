@@ -1,10 +1,13 @@
+use crate::aabb::Aabb;
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
-use crate::common::SP;
+use crate::common::{self, SP};
+use crate::sampler::Sampler;
 use crate::ray::Ray;
 use crate::{unit_vector, dot, cross};
 use crate::vec3::{self, Vec3, Point3, Scalar};
 
+#[derive(Clone)]
 pub struct Quad {
     origin: Point3,
     u: Vec3,
@@ -12,7 +15,9 @@ pub struct Quad {
     normal: Vec3,
     w: Vec3,
     d: Scalar,
+    area: Scalar,
     mat: SP<dyn Material>,
+    bbox: Aabb,
 }
 
 impl Quad {
@@ -21,6 +26,8 @@ impl Quad {
         let normal = unit_vector(n);
         let d = vec3::dot(normal, origin);
         let w = n / n.length_squared();
+        let bbox = Aabb::surrounding(&Aabb::new(origin, origin + u + v),
+                                     &Aabb::new(origin + u, origin + v)).padded();
         Quad{
             origin,
             u,
@@ -28,7 +35,9 @@ impl Quad {
             normal,
             w,
             d,
+            area: n.length(),
             mat,
+            bbox,
         }
     }
 }
@@ -49,26 +58,49 @@ impl Hittable for Quad {
             return false;
         }
 
-        rec.t = t;
-        rec.p = ray.at(t);
-        let o_p: Vec3 = rec.p - self.origin;
+        let p = ray.at(t);
+        let o_p: Vec3 = p - self.origin;
 
         // inside quad ?
         let alpha = dot(self.w, cross(o_p, self.v));
-        if alpha < 0.0 || alpha > 1.0 {
+        if !(0.0..=1.0).contains(&alpha) {
             return false;
         }
         let beta = dot(self.w, cross(self.u, o_p));
-        if beta < 0.0 || beta > 1.0 {
+        if !(0.0..=1.0).contains(&beta) {
             return false;
         }
 
-        let outward_normal = if dot_nd < 0.0 { -self.normal} else { self.normal };
-        rec.set_face_normal(ray, outward_normal);
+        // only fill the record once the hit is confirmed, a BVH node may already hold a closer hit in it
+        rec.t = t;
+        rec.p = p;
+        // the front is the side of cross(u, v)
+        rec.set_face_normal(ray, self.normal);
         rec.mat = Some(self.mat.clone());
 
         rec.uv = Vec3::new(alpha, beta, 0.0);
 
         true
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> Scalar {
+        let mut rec = HitRecord::new();
+        if !self.hit(&Ray::new(origin, direction), 0.001, common::INFINITY, &mut rec) {
+            return 0.0;
+        }
+
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (dot(direction, self.normal) / direction.length()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random_direction(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (r1, r2) = sampler.get_2d();
+        let p = self.origin + r1 * self.u + r2 * self.v;
+        p - origin
+    }
 }
\ No newline at end of file
@@ -1,268 +1,345 @@
-use crate::quad::Quad;
-use crate::{material::*, to_spherical};
-use crate::{color, ray::*, vec3};
-use crate::camera::Camera;
-
 use crate::settings::*;
 use crate::hittable::*;
-use crate::hittable_list::*;
-use crate::common::{self, random_double, SP, sigmoid};
-
-use crate::color::{Color, WriteColor};
-use crate::vec3::{Point3, Vec3};
+use crate::hittable_list::HittableList;
+use crate::common;
+use crate::integrator::{Integrator, EPSILON};
+
+use crate::accumulator::{Accumulator, PixelSum, Splat};
+use crate::aov::{AovBuffers, AovPixel, AovSample, MaterialIds};
+use crate::color::Color;
+use crate::framebuffer::FrameBuffer;
+use crate::tile::{make_tiles, Tile};
 use crate::vec3::Scalar;
-use crate::vec3::*;
-use crate::sphere::Sphere;
 
 use rayon::prelude::*;
-use image::RgbImage;
 use indicatif::{ProgressBar, ProgressStyle, ProgressState};
 use std::fmt::Write;
-
-use exr::prelude::Vec2;
-
-fn get_image_pixel(settings: &Settings, spherical: Vec3) -> Color {
-    let image = settings.env_map.as_ref().unwrap();
-    let size = image.layer_data.size;
-    let pos_px = Vec2(  (spherical.y() * (size.0 as Scalar)) as usize,
-                       (spherical.z() * (size.1 as Scalar)) as usize);
-    let pixel = image.layer_data.channel_data.pixels.get_pixel(pos_px);
-    Color::new(pixel.0.to_f32().into(), pixel.1.to_f32().into(), pixel.2.to_f32().into())
+use std::io;
+use std::sync::Mutex;
+use std::time::Instant;
+
+// What the rays are traced against
+pub struct RenderScene<'a> {
+    pub world: &'a dyn Hittable,
+    pub lights: &'a HittableList,
+    pub material_ids: &'a MaterialIds,
 }
 
-
-fn sky_color_hdri(ray: &Ray, settings: &Settings) -> Color {
-    let mut unit_direction = vec3::unit_vector(ray.direction());
-    unit_direction.e[1] = unit_direction.y();
-    let mut sph = to_spherical(unit_direction);
-    
-    sph.e[1] %= 2.0 * common::PI;
-
-    sph.e[2] += 2.0*common::PI;
-    sph.e[2] %= 2.0 * common::PI;
-
-    get_image_pixel(settings, sph  / (2.0 * common::PI) )
+// the random numbers only depend on the seed, the pixel and the sample index.
+// Returns the color and where the sample is on the image, in pixels from the top left corner.
+fn compute_color(settings: &Settings, integrator: &dyn Integrator, x: u32, y: u32, sample: u32, scene: &RenderScene, aov: Option<&mut AovPixel>) -> (Color, Scalar, Scalar) {
+    let mut sampler = settings.sampler.sampler(settings.seed, settings.sample_per_pixel, x, y, sample);
+    let (dx, dy) = sampler.get_2d();
+    let u = ((x as Scalar) + dx) / ((settings.image_width-1) as f64);
+    let v = (((settings.image_height-y) as Scalar) + dy) / ((settings.image_height-1) as f64);
+    // v goes up the image
+    let film = (x as Scalar + dx, y as Scalar + 1.0 - dy);
+
+    let r = settings.camera.get_ray(u, v, sampler.as_mut());
+    if let Some(aov) = aov {
+        let mut rec = HitRecord::new();
+        let sample = if scene.world.hit(&r, EPSILON, common::INFINITY, &mut rec) {
+            AovSample::new(&r, &rec, scene.material_ids)
+        } else {
+            AovSample::default()
+        };
+        aov.add(&settings.aovs, &sample);
+    }
+    let c = integrator.radiance(r, scene, settings, sampler.as_mut());
+    (c, film.0, film.1)
 }
 
-fn sky_color(ray: &Ray, settings: &Settings) -> Color {
-    let unit_direction = vec3::unit_vector(ray.direction());
-    let mut t = 0.5 * (unit_direction.y() + 1.0);
-    const COLOR1: Color = color::white();
-    const COLOR2: Color = Color::new(0.5, 0.7, 1.0);
-    //t = t * t * t;
-    //t = Scalar::powf(t, 0.5);
-    t*=1.5;
-    //t = sigmoid(t+0.5, 2.0);
-    
-    (1.0 - t) * COLOR1 + t * COLOR2
+// Samples of the pixels of a tile, and their AOVs when it is their first pass.
+// The splats cover the tile and the margin its samples reach through the filter.
+struct TileResult {
+    pixels: Vec<PixelSum>,
+    aovs: Vec<AovPixel>,
+    splat_area: Tile,
+    splats: Vec<Splat>,
 }
 
-fn ray_color(ray: &Ray, world: &dyn Hittable, settings: &Settings, depth: u32) -> Color {
-
-    if depth <= 0 {
-        return color::black();
-    }
-
-    let mut rec = HitRecord::new();
-    const EPSILON: Scalar = 0.0001;
-
-    if world.hit(ray, EPSILON, common::INFINITY, &mut rec) {
-        let mut attenuation = Color::default();
-        let mut scattered = Ray::default();
-
-        //return rec.uv; // todo : pattern matching to switch pass ?
-        //return vec3::fit01(vec3::unit_vector(rec.p)); // todo : pattern matching to switch pass ?
-        //return vec3::fit01(vec3::unit_vector(rec.normal)); // todo : pattern matching to switch pass ?
-        if rec.mat.as_ref().unwrap().
-            scatter(ray, &rec, &mut attenuation, &mut scattered)
-        {
-            return attenuation * ray_color(&scattered, world, settings, depth - 1);
-        }   
-        return color::black();   
+// adds the sample to the pixels whose filter covers it
+fn splat(settings: &Settings, area: &Tile, splats: &mut [Splat], c: Color, sx: Scalar, sy: Scalar) {
+    let filter = &settings.filter;
+    let radius = filter.radius();
+    let x_min = ((sx - radius - 0.5).floor().max(area.x0 as Scalar)) as u32;
+    let y_min = ((sy - radius - 0.5).floor().max(area.y0 as Scalar)) as u32;
+    let x_max = ((sx + radius - 0.5).floor() as u32).min(area.x1 - 1);
+    let y_max = ((sy + radius - 0.5).floor() as u32).min(area.y1 - 1);
+    for y in y_min..=y_max {
+        for x in x_min..=x_max {
+            let weight = filter.weight(x as Scalar + 0.5 - sx, y as Scalar + 0.5 - sy);
+            if weight != 0.0 {
+                splats[((y - area.y0) * area.width() + x - area.x0) as usize].add(c, weight);
+            }
+        }
     }
-    sky_color(ray, settings)
-}
-
-fn compute_color(settings: &Settings, u: Scalar, v: Scalar, world: &dyn Hittable) -> Color {
-    let r = settings.camera.get_ray(u, v);
-    ray_color(&r, world, settings, settings.max_depth)
-
 }
 
-fn generate_world_generic() -> HittableList
-{
-    let mut world = HittableList::new();
-
-    let lambert_blue = SP::new(Lambertian::new(Color::new(0.1, 0.2, 0.8), None));
-    let lambert_red = SP::new(Lambertian::new(Color::new(0.8, 0.3, 0.2), Some(0.005)));
-    let lambert_red_checker = SP::new(Lambertian::new(Color::new(0.8, 0.3, 0.2), Some(0.005)));
-    let lambert_dark = SP::new(Lambertian::new(Color::new(0.06, 0.05, 0.05), Some(0.1)));
-    let metal_red = SP::new(Metal::new(Color::new(0.8, 0.5, 0.3), 0.9));
-    let metal_green = SP::new(Metal::new(Color::new(0.6, 0.8, 0.65), 0.5));
-    let metal_white_fuzz = SP::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.));
-    let metal_white_reflect = SP::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.001));
-    let glass = SP::new(Dielectric::new(1.5));
-    world.add(Box::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, lambert_blue.clone())));
-    world.add(Box::new(Sphere::new(Point3::new(-1.0, 1.5, -3.0), 0.6, lambert_blue)));
-    world.add(Box::new(Sphere::new(Point3::new(4.5, 1.7, -4.0), 1.0, metal_red.clone())));
-    world.add(Box::new(Sphere::new(Point3::new(-1.2, 0.05, -1.0), 0.5, glass.clone())));
-    world.add(Box::new(Sphere::new(Point3::new(0.35, -0.4, -0.7), 0.1, metal_white_reflect.clone())));
-    world.add(Box::new(Sphere::new(Point3::new(0.55, -0.4, -0.8), 0.1, metal_green.clone())));
-    world.add(Box::new(Sphere::new(Point3::new(-0.55, -0.4, -0.75), 0.1, metal_green.clone())));
-    world.add(Box::new(Sphere::new(Point3::new(-0.40, -0.47, -0.7), 0.03, metal_white_reflect.clone())));
-    world.add(Box::new(Sphere::new(Point3::new(-0.38, -0.47, -0.75), 0.03, metal_white_reflect.clone())));
-    world.add(Box::new(Sphere::new(Point3::new(-0.33, -0.47, -0.72), 0.03, metal_white_reflect.clone())));
-    world.add(Box::new(Sphere::new(Point3::new(0.5, 0.8, -1.3), 0.35, metal_green)));
-    world.add(Box::new(Sphere::new(Point3::new(-0.85, 0.5, -1.2), -0.25, glass)));
-    world.add(Box::new(Sphere::new(Point3::new(1.55, 1.2, -1.9), 0.4, metal_white_reflect.clone())));
-    world.add(Box::new(Sphere::new(Point3::new(1.55, 1.2, -1.9), 0.4, metal_white_reflect)));
-    world.add(Box::new(Sphere::new(Point3::new(0.95, 0.4, -0.15), 0.15, metal_white_fuzz.clone())));
-    world.add(Box::new(Sphere::new(Point3::new(1.15, 0.2, -0.12), 0.08, metal_white_fuzz.clone())));
-    world.add(Box::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, lambert_red_checker.clone())));
-    //world.add(Box::new(Sphere::new(Point3::new(-15.0, 27.0, -5.0), 25.0, lambert_dark)));
-
-
-    return world;
+// pixels are given their next `counts` samples, starting at the index `first`, both indexed like the image
+fn render_tile(settings: &Settings, tile: &Tile, first: &[u32], counts: &[u32], scene: &RenderScene) -> TileResult {
+    let splat_area = tile.expand(settings.filter.margin(), settings.image_width, settings.image_height);
+    let mut result = TileResult {
+        pixels: Vec::with_capacity(tile.nb_pixels() as usize),
+        aovs: Vec::new(),
+        splat_area,
+        splats: vec![Splat::default(); splat_area.nb_pixels() as usize],
+    };
+    let integrator = settings.integrator.integrator(settings);
+    for y in tile.y0..tile.y1 {
+        for x in tile.x0..tile.x1 {
+            let mut pixel = PixelSum::default();
+            let index = (y * settings.image_width + x) as usize;
+            let first = first[index];
+            let mut aov = (first == 0 && !settings.aovs.is_empty()).then(AovPixel::default);
+            for sample in first..first + counts[index] {
+                let (c, sx, sy) = compute_color(settings, integrator.as_ref(), x, y, sample, scene, aov.as_mut());
+                pixel.add(c);
+                splat(settings, &splat_area, &mut result.splats, c, sx, sy);
+            }
+            result.pixels.push(pixel);
+            if let Some(aov) = aov {
+                result.aovs.push(aov);
+            }
+        }
+    }
+    result
 }
 
-fn generate_world_planes() -> HittableList
-{
-    
-    let mut world = HittableList::new();
-
-    let lambert_blue = SP::new(Lambertian::new(Color::new(0.1, 0.2, 0.8), None));
-    let lambert_green = SP::new(Lambertian::new(Color::new(0.5, 0.8, 0.6), None));
-    let lambert_red_plane = SP::new(Lambertian::new(Color::new(0.8, 0.3, 0.2), Some(0.2)));
-    let lambert_red_checker = SP::new(Lambertian::new(Color::new(0.8, 0.3, 0.2), Some(0.5)));
-    let lambert_dark = SP::new(Lambertian::new(Color::new(0.06, 0.05, 0.05), Some(0.1)));
-    let lambert_light = SP::new(Lambertian::new(Color::new(0.95, 0.95, 0.95), Some(0.1)));
-    let metal_red = SP::new(Metal::new(Color::new(0.8, 0.5, 0.3), 0.9));
-    let metal_green = SP::new(Metal::new(Color::new(0.6, 0.8, 0.65), 0.5));
-    let metal_white_little_fuzz = SP::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.05));
-    let metal_white_reflect = SP::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.001));
-    let glass = SP::new(Dielectric::new(1.5));
-    let bubble = SP::new(Dielectric::new(-1.5));
-
-    world.add(Box::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, metal_white_reflect.clone())));
-    world.add(Box::new(Sphere::new(Point3::new(-1.2, 0.05, -1.0), 0.5, lambert_blue.clone())));
-    world.add(Box::new(Sphere::new(Point3::new(1.0, -0.4, -1.0), 0.1, lambert_green.clone())));
-    let plane_scale = 3.5;
-    world.add(Box::new(Quad::new(Point3::new(-0.5*plane_scale,-0.5, -0.5*plane_scale -1.0),
-    Vec3::new(0.0, 0.0, plane_scale),
-                                                Vec3::new(plane_scale, 0.0, 0.0),
-                                                lambert_red_plane.clone())));
-    
-    world.add(Box::new(Quad::new(Point3::new(2.0, -1.0, -1.5),
-                                                Vec3::new(0.0,  3.0, 0.0),
-                                                Vec3::new(-2.0, 0.0, -2.0),
-                                                lambert_light.clone())));
-
-    let angle = common::degrees_to_radians(45.0);
-    world.add(Box::new(Quad::new(Point3::new(0.6, 0.0, -1.0),
-                                    0.5*Vec3::new(1.0,  0.0, 0.0),
-                                    0.5*Vec3::new(0.0, Scalar::cos(angle), Scalar::sin(angle)),
-                                    metal_white_reflect.clone())));
-
-                                    let angle3 = common::degrees_to_radians(-45.0);
-    world.add(Box::new(Quad::new(Point3::new(0.5, 0.75, -0.5),
-                                    0.5*Vec3::new(0.0,  0.0, 1.0),
-                                    0.5*Vec3::new(Scalar::cos(angle3), Scalar::sin(angle3), 0.0),
-                                    metal_white_reflect.clone())));
-                                          
-    return world;
+fn nb_samples(pixels: &[PixelSum]) -> u64 {
+    pixels.iter().map(|p| p.samples as u64).sum()
 }
 
-fn generate_world_debug() -> HittableList
-{
-    let mut world = HittableList::new();
-
-    let r = Scalar::cos(common::PI / 4.0);
-
-    let material_left = SP::new(Lambertian::new(Color::new(0.0, 0.0, 1.0), None));
-    let material_right = SP::new(Lambertian::new(Color::new(1.0, 0.0, 0.0), None));
-
-    world.add(Box::new(Sphere::new(
-        Point3::new(-r, 0.0, -1.0),
-        r,
-        material_left,
-    )));
-    world.add(Box::new(Sphere::new(
-        Point3::new(r, 0.0, -1.0),
-        r,
-        material_right,
-    )));
-
-    return world;
+fn write_tile(tile: &Tile, result: &TileResult, acc: &mut Accumulator, aovs: &mut AovBuffers) {
+    acc.add_tile(tile, &result.pixels);
+    for (i, aov) in result.aovs.iter().enumerate() {
+        let i = i as u32;
+        aovs.set(tile.x0 + i % tile.width(), tile.y0 + i / tile.width(), aov);
+    }
 }
 
-fn render_sequential(   settings: &Settings, 
-                        world: &HittableList, 
-                        progress_bar: &ProgressBar,
-                        img: &mut RgbImage)
+fn render_pass_sequential(  settings: &Settings, 
+                            scene: &RenderScene,
+                            tiles: &[Tile],
+                            counts: &[u32],
+                            progress_bar: &ProgressBar,
+                            acc: &mut Accumulator,
+                            aovs: &mut AovBuffers)
 {
-    for (x, y, pixel) in img.enumerate_pixels_mut() {
-        let mut pixel_color = color::black();
-        for _ in 0..settings.sample_per_pixel {
-            let u = ((x as Scalar) + random_double()) / ((settings.image_width-1) as f64);
-            let v = (((settings.image_height-y) as Scalar) + random_double()) / ((settings.image_height-1) as f64);
-
-            pixel_color += compute_color(&settings, u, v, world);
-        }
-
-        pixel.write_color(pixel_color / (settings.sample_per_pixel as Scalar));
-        progress_bar.inc(1);
+    let first = acc.sample_counts();
+    for tile in tiles {
+        let result = render_tile(settings, tile, &first, counts, scene);
+        write_tile(tile, &result, acc, aovs);
+        acc.add_splats(&result.splat_area, &result.splats);
+        progress_bar.inc(nb_samples(&result.pixels));
     }
 }
 
-fn render_parallel( settings: &Settings, 
-                    world: &HittableList, 
-                    progress_bar: &ProgressBar,
-                    img: &mut RgbImage)
+// rayon hands the tiles to idle threads, each one writes its tile as soon as it is done.
+// The splats are added in the order of the tiles after the pass, so that the sums do not depend on the threads.
+fn render_pass_parallel(settings: &Settings, 
+                        scene: &RenderScene,
+                        tiles: &[Tile],
+                        counts: &[u32],
+                        progress_bar: &ProgressBar,
+                        acc: &mut Accumulator,
+                        aovs: &mut AovBuffers)
 {
-    for j in (0..settings.image_height).rev() {
-        let pixel_colors: Vec<_> = (0..settings.image_width)
-        .into_par_iter()
-        .map(|i| {
-            let mut pixel_color = color::black();
-            for _ in 0..settings.sample_per_pixel {
-                let u = ((i as Scalar) + random_double()) / ((settings.image_width-1) as f64);
-                let v = (((settings.image_height-j) as Scalar) + random_double()) / ((settings.image_height-1) as f64);
-    
-                pixel_color += compute_color(&settings, u, v, world);
-            }
-            pixel_color / (settings.sample_per_pixel as Scalar)
-        })
-        .collect();
-
-        for (i, pixel_color) in pixel_colors.iter().enumerate() {
-            img.put_pixel(i.try_into().unwrap(), j, color::to_rgb(*pixel_color));
-        }
-        progress_bar.inc(settings.image_width.into());
-
+    // the tiles do not overlap : the samples done so far of their pixels are read before the pass
+    let first = acc.sample_counts();
+    let output = Mutex::new((&mut *acc, aovs));
+    let splats: Vec<(Tile, Vec<Splat>)> = tiles.par_iter().map(|tile| {
+        let result = render_tile(settings, tile, &first, counts, scene);
+        let mut output = output.lock().unwrap();
+        let (acc, aovs) = &mut *output;
+        write_tile(tile, &result, acc, aovs);
+        progress_bar.inc(nb_samples(&result.pixels));
+        (result.splat_area, result.splats)
+    }).collect();
+    for (area, splats) in &splats {
+        acc.add_splats(area, splats);
     }
 }
 
-pub fn render(settings: &Settings, img: &mut RgbImage)
+// Renders passes of samples until one of the budgets is spent, a single pass when not progressive.
+// With adaptive sampling, the passes after the first one favor the pixels with the highest error.
+// The AOVs are averaged over the samples of the first pass.
+// write_image is given the image of the passes done so far, every write_interval seconds.
+pub fn render( settings: &Settings,
+               scene: &RenderScene,
+               img: &mut FrameBuffer,
+               aovs: &mut AovBuffers,
+               write_image: &mut dyn FnMut(&FrameBuffer) -> io::Result<()>) -> io::Result<Accumulator>
 {
-    let total_nb_pixels = settings.image_width * settings.image_height; 
-    let progress_bar = ProgressBar::new(total_nb_pixels.into());
+    let nb_pixels = (settings.image_width * settings.image_height) as u64;
+    let budget = nb_pixels * settings.sample_per_pixel as u64;
+    let progress_bar = ProgressBar::new(budget);
     progress_bar.set_message("Render");
     progress_bar.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{bar:.cyan/blue}] {percent}% ({eta})")
         .unwrap()
         .with_key("eta", |state: &ProgressState, w: &mut dyn Write| write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap()));
 
+    let tiles = make_tiles(settings.image_width, settings.image_height, settings.tile_size, settings.tile_order);
+    let mut acc = Accumulator::new(settings.image_width, settings.image_height);
+    let progressive = settings.progressive || settings.adaptive;
+
+    let start = Instant::now();
+    let mut last_write = Instant::now();
+    let mut used = 0;
+    let mut stop_reason = "sample budget spent";
+    while used < budget {
+        let remaining = budget - used;
+        let counts = if settings.adaptive && used > 0 {
+            acc.adaptive_counts(nb_pixels * settings.pass_samples as u64, remaining, settings.noise_threshold)
+        }
+        else {
+            let n = if settings.adaptive {
+                settings.min_samples()
+            } else if progressive {
+                settings.pass_samples
+            } else {
+                settings.sample_per_pixel
+            };
+            vec![n.min((remaining / nb_pixels) as u32); nb_pixels as usize]
+        };
+        let pass_samples: u64 = counts.iter().map(|&n| n as u64).sum();
+        if pass_samples == 0 {
+            stop_reason = "noise threshold reached";
+            break;
+        }
+        used += pass_samples;
 
-    //let world = generate_world_generic();
-    //let world = generate_world_generic();
-    let world = generate_world_planes();
+        if !settings.parallel {
+            render_pass_sequential(settings, scene, &tiles, &counts, &progress_bar, &mut acc, aovs);
+        }
+        else {
+            render_pass_parallel(settings, scene, &tiles, &counts, &progress_bar, &mut acc, aovs);
+        }
 
-    if !settings.parallel {
-        render_sequential(settings, &world, &progress_bar, img);
-    }
-    else {
-        render_parallel(settings, &world, &progress_bar, img);
+        if !progressive {
+            break;
+        }
+        if settings.time_budget.is_some_and(|budget| start.elapsed().as_secs_f64() >= budget) {
+            stop_reason = "time budget spent";
+            break;
+        }
+        if settings.noise_threshold.is_some_and(|threshold| acc.converged(threshold)) {
+            stop_reason = "noise threshold reached";
+            break;
+        }
+        if used < budget && last_write.elapsed().as_secs_f64() >= settings.write_interval {
+            acc.resolve(img);
+            write_image(img)?;
+            last_write = Instant::now();
+        }
     }
+    acc.resolve(img);
 
     progress_bar.finish();
+    if progressive {
+        println!("Progressive render stopped, {} after {:.1} samples per pixel on average", stop_reason, used as f64 / nb_pixels as f64);
+    }
+    Ok(acc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterKind;
+    use crate::scene::{self, Scene};
+    use std::path::Path;
+
+    fn small_scene() -> Scene {
+        let source = r#"
+            [settings]
+            image_width = 48
+            image_height = 32
+            sample_per_pixel = 4
+            tile_size = 8
+            max_depth = 4
+            filter = "mitchell"
+
+            [camera]
+            look_from = [0, 0.5, 2]
+            look_at = [0, 0, -1]
+            vfov = 60
+
+            [materials]
+            red = { type = "lambertian", albedo = "red" }
+            glass = { type = "dielectric", ir = 1.5 }
+            chrome = { type = "metal", albedo = [0.8, 0.8, 0.8], fuzz = 0.2 }
+            lamp = { type = "diffuse_light", emit = "white", intensity = 4 }
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, -1]
+            radius = 0.5
+            material = "red"
+
+            [[objects]]
+            type = "sphere"
+            center = [1, 0, -1]
+            radius = 0.4
+            material = "glass"
+
+            [[objects]]
+            type = "quad"
+            origin = [-3, -0.5, -4]
+            u = [6, 0, 0]
+            v = [0, 0, 5]
+            material = "chrome"
+
+            [[objects]]
+            type = "quad"
+            origin = [-1, 2, -2]
+            u = [2, 0, 0]
+            v = [0, 0, 1]
+            material = "lamp"
+        "#;
+        match scene::parse_scene(source, Path::new("")) {
+            Ok(scene) => scene,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn render_image(scene: &Scene, settings: &Settings) -> Vec<[Scalar; 3]> {
+        let render_scene = RenderScene { world: &scene.world, lights: &scene.lights, material_ids: &scene.material_ids };
+        let mut img = FrameBuffer::new(settings.image_width, settings.image_height);
+        let mut aovs = AovBuffers::new(&settings.aovs, settings.image_width, settings.image_height);
+        render(settings, &render_scene, &mut img, &mut aovs, &mut |_| Ok(())).unwrap();
+        (0..img.height)
+            .flat_map(|y| (0..img.width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let c = img.get(x, y);
+                [c.x(), c.y(), c.z()]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_sequential_and_parallel() {
+        let scene = small_scene();
+        assert!(scene.settings.filter.kind == FilterKind::Mitchell);
+        let mut settings = scene.settings.clone();
+        // the progressive render takes four passes
+        settings.pass_samples = 1;
+        for progressive in [false, true] {
+            settings.progressive = progressive;
+            settings.parallel = false;
+            let sequential = render_image(&scene, &settings);
+            settings.parallel = true;
+            let parallel = render_image(&scene, &settings);
+            assert!(sequential == parallel, "progressive {}", progressive);
+        }
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn other_seed_other_image() {
+        let scene = small_scene();
+        let mut settings = scene.settings.clone();
+        let first = render_image(&scene, &settings);
+        assert!(first == render_image(&scene, &settings));
+        settings.seed = 1;
+        assert!(first != render_image(&scene, &settings));
+    }
+}
@@ -0,0 +1,231 @@
+use clap::ValueEnum;
+
+use crate::common::{self, Rng};
+use crate::vec3::Scalar;
+
+// Source of the random numbers of one sample of one pixel.
+// Each call takes the next dimensions : the pixel position first, then the lens, then the bounces.
+pub trait Sampler {
+    fn get_1d(&mut self) -> Scalar;
+    fn get_2d(&mut self) -> (Scalar, Scalar);
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum SamplerKind {
+    // uniform random numbers
+    Independent,
+    // jittered strata of the sample_per_pixel samples of a pixel
+    Stratified,
+    // radical inverses in prime bases, randomly shifted per pixel
+    Halton,
+    // Owen scrambled Sobol points, shuffled per dimension
+    Sobol,
+}
+
+impl SamplerKind {
+    // spp is the number of samples the strata are made for
+    pub fn sampler(&self, seed: u64, spp: u32, x: u32, y: u32, sample: u32) -> Box<dyn Sampler> {
+        let rng = common::sample_rng(seed, x, y, sample);
+        let pixel_seed = common::mix(common::mix(seed) ^ (((y as u64) << 32) | x as u64));
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler { rng }),
+            SamplerKind::Stratified => Box::new(StratifiedSampler { rng, pixel_seed, spp: spp.max(1), sample, dimension: 0 }),
+            SamplerKind::Halton => Box::new(HaltonSampler { rng, pixel_seed, sample, dimension: 0 }),
+            SamplerKind::Sobol => Box::new(SobolSampler { pixel_seed, sample, dimension: 0 }),
+        }
+    }
+}
+
+// a dimension of the current pixel, hashed into a 32 bits seed
+fn dimension_seed(pixel_seed: u64, dimension: u32) -> u32 {
+    common::mix(pixel_seed ^ ((dimension as u64) << 40)) as u32
+}
+
+// [0, 1) from the 32 bits of x
+fn to_unit(x: u32) -> Scalar {
+    x as Scalar / 4294967296.0
+}
+
+pub struct IndependentSampler {
+    rng: Rng,
+}
+
+impl Sampler for IndependentSampler {
+    fn get_1d(&mut self) -> Scalar {
+        common::random_double(&mut self.rng)
+    }
+
+    fn get_2d(&mut self) -> (Scalar, Scalar) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// Kensler's hash based permutation of [0, n), one per seed
+fn permute(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            return i.wrapping_add(seed) % n;
+        }
+    }
+}
+
+// The spp samples of a pixel fall in distinct strata of every dimension, in a different order for each one.
+// The samples past spp (adaptive passes) start over in a new permutation.
+pub struct StratifiedSampler {
+    rng: Rng,
+    pixel_seed: u64,
+    spp: u32,
+    sample: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    fn stratum(&mut self, n: u32) -> u32 {
+        let round = self.sample / self.spp;
+        let seed = dimension_seed(self.pixel_seed ^ ((round as u64) << 20), self.dimension);
+        self.dimension += 1;
+        permute(self.sample % self.spp, n, seed)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn get_1d(&mut self) -> Scalar {
+        let stratum = self.stratum(self.spp);
+        (stratum as Scalar + common::random_double(&mut self.rng)) / self.spp as Scalar
+    }
+
+    fn get_2d(&mut self) -> (Scalar, Scalar) {
+        // nx * ny strata, at least spp of them
+        let nx = (self.spp as Scalar).sqrt().ceil() as u32;
+        let ny = self.spp.div_ceil(nx);
+        let stratum = self.stratum(nx * ny);
+        let u = ((stratum % nx) as Scalar + common::random_double(&mut self.rng)) / nx as Scalar;
+        let v = ((stratum / nx) as Scalar + common::random_double(&mut self.rng)) / ny as Scalar;
+        (u, v)
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+fn radical_inverse(base: u32, mut i: u32) -> Scalar {
+    let inv_base = 1.0 / base as Scalar;
+    let mut inv_base_n = 1.0;
+    let mut reversed: u64 = 0;
+    while i > 0 {
+        let next = i / base;
+        reversed = reversed * base as u64 + (i - next * base) as u64;
+        inv_base_n *= inv_base;
+        i = next;
+    }
+    (reversed as Scalar * inv_base_n).min(1.0 - Scalar::EPSILON)
+}
+
+// Cranley-Patterson rotation of the Halton sequence, independent numbers past the table of primes
+pub struct HaltonSampler {
+    rng: Rng,
+    pixel_seed: u64,
+    sample: u32,
+    dimension: u32,
+}
+
+impl Sampler for HaltonSampler {
+    fn get_1d(&mut self) -> Scalar {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => {
+                let shift = to_unit(dimension_seed(self.pixel_seed, dimension));
+                (radical_inverse(base, self.sample) + shift).fract()
+            }
+            None => common::random_double(&mut self.rng),
+        }
+    }
+
+    fn get_2d(&mut self) -> (Scalar, Scalar) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// first two dimensions of the Sobol sequence, the matrix of the first one is the identity
+fn sobol(index: u32, dimension: u32) -> u32 {
+    let mut x = 0;
+    let mut v: u32 = 1 << 31;
+    for bit in 0..32 {
+        if index & (1 << bit) != 0 {
+            x ^= if dimension == 0 { 1 << (31 - bit) } else { v };
+        }
+        v ^= v >> 1;
+    }
+    x
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+// Owen scrambling, the bits are reversed so that the permutation of a bit only depends on the higher ones
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// 2D Sobol points padded to any number of dimensions (Burley 2020) : every pair of dimensions
+// goes through the samples of the pixel in its own order, which keeps them uncorrelated
+pub struct SobolSampler {
+    pixel_seed: u64,
+    sample: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    fn point(&mut self) -> (u32, u32) {
+        let seed = dimension_seed(self.pixel_seed, self.dimension);
+        self.dimension += 1;
+        let index = nested_uniform_scramble(self.sample, seed);
+        let x = nested_uniform_scramble(sobol(index, 0), seed ^ 0x5bd1e995);
+        let y = nested_uniform_scramble(sobol(index, 1), seed ^ 0x1b873593);
+        (x, y)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn get_1d(&mut self) -> Scalar {
+        to_unit(self.point().0)
+    }
+
+    fn get_2d(&mut self) -> (Scalar, Scalar) {
+        let (x, y) = self.point();
+        (to_unit(x), to_unit(y))
+    }
+}
@@ -0,0 +1,1076 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::aov::{Aov, AovOutput, MaterialIds};
+use crate::camera::Camera;
+use crate::color::{self, Color, ToneMapping};
+use crate::common::SP;
+use crate::env_map::EnvMap;
+use crate::hittable::{Hittable, Tagged};
+use crate::hittable_list::HittableList;
+use crate::material::*;
+use crate::microfacet::{CoatedDiffuse, Conductor, Principled};
+use crate::obj;
+use crate::filter::FilterKind;
+use crate::integrator::IntegratorKind;
+use crate::sampler::SamplerKind;
+use crate::noise::{Fractal, NoiseKind};
+use crate::texture::{self, Checker2D, Checker3D, ImageTexture, MarbleTexture, NoiseTexture, Texture, WoodTexture, WrapMode};
+use crate::tile::TileOrder;
+use crate::quad::Quad;
+use crate::settings::{Background, Settings};
+use crate::sphere::Sphere;
+use crate::triangle::Triangle;
+use crate::vec3::{Point3, Scalar, Vec3};
+
+// Scene description format, a TOML file :
+//
+//   [settings]
+//   image_width = 400
+//   sample_per_pixel = 16
+//   max_depth = 10
+//   parallel = true
+//   tile_size = 32
+//   tile_order = "spiral"
+//   background = "sky"
+//   light_sampling = true
+//   tone_mapping = "aces"
+//   exposure = 0.0
+//   seed = 0
+//   roulette_diffuse = 3
+//   roulette_specular = 5
+//   roulette_transmission = 8
+//   sampler = "sobol"
+//   filter = "gaussian"
+//   filter_radius = 1.5
+//   integrator = "path"
+//   ao_distance = 1.0
+//   progressive = true
+//   pass_samples = 4
+//   time_budget = 60.0
+//   noise_threshold = 0.02
+//   write_interval = 10.0
+//   adaptive = true
+//   min_samples = 4
+//   sample_heat_map = true
+//   aovs = ["albedo", "normal", "position", "depth", "uv", "material-id", "object-id"]
+//   aov_output = "layers"
+//   denoise = true
+//   keep_raw = true
+//
+//   [environment]
+//   file = "hdri/sky.exr"
+//   rotation = 90.0
+//   intensity = 1.0
+//
+//   [camera]
+//   look_from = [0, 0.8, 1]
+//   look_at = [0, 0, -1]
+//   vup = [0, 1, 0]
+//   vfov = 50.0
+//   aperture = 0.0
+//   focus_dist = 2.0
+//
+//   [textures]
+//   tiles = { type = "checker", even = "white", odd = [0.2, 0.2, 0.2], scale = 10.0 }
+//   cubes = { type = "checker3d", even = "red", odd = "white", scale = 4.0 }
+//   wood = { type = "image", file = "textures/wood.png", wrap = "repeat" }
+//   clouds = { type = "noise", noise = "fbm", color = [0.8, 0.3, 0.2], scale = 1.0, octaves = 6, lacunarity = 2.0, gain = 0.5 }
+//   stone = { type = "marble", base = "white", vein = [0.2, 0.2, 0.25], scale = 4.0, turbulence = 5.0 }
+//   oak = { type = "wood", light = [0.8, 0.6, 0.4], dark = [0.4, 0.25, 0.1], rings = 10.0, scale = 2.0, turbulence = 1.0 }
+//
+//   [materials]
+//   blue = { type = "lambertian", albedo = [0.1, 0.2, 0.8] }
+//   floor = { type = "lambertian", albedo = "tiles" }
+//   chrome = { type = "metal", albedo = [0.8, 0.8, 0.8], fuzz = 0.001 }
+//   glass = { type = "dielectric", ir = 1.5 }
+//   wine = { type = "dielectric", ir = 1.33, transmittance = [0.5, 0.02, 0.08], distance = 0.2, tint = "white" }
+//   ink = { type = "dielectric", ir = 1.5, absorption = [4, 2, 0.5] }
+//   plastic = { type = "principled", base_color = [0.8, 0.1, 0.1], metallic = 0.0, roughness = 0.3, ior = 1.5 }
+//   gold = { type = "principled", conductor = "gold", roughness = 0.2 }
+//   lacquer = { type = "coated_diffuse", albedo = "oak", ior = 1.5, roughness = 0.05 }
+//   worn = { type = "mix", a = "chrome", b = "lacquer", amount = 0.3 }
+//   stained = { type = "mix", a = "chrome", b = "lacquer", mask = "clouds" }
+//   lamp = { type = "diffuse_light", emit = "white", intensity = 4.0 }
+//
+//   [[objects]]
+//   type = "sphere"
+//   center = [0, 0, -1]
+//   radius = 0.5
+//   material = "blue"
+//
+//   [[objects]]
+//   type = "quad"
+//   origin = [-1, -0.5, -2]
+//   u = [0, 0, 2]
+//   v = [2, 0, 0]
+//   material = "chrome"
+//
+//   [[objects]]
+//   type = "triangle"
+//   a = [0, 0, 0]
+//   b = [1, 0, 0]
+//   c = [0, 1, 0]
+//   material = "blue"
+//
+//   [[objects]]
+//   type = "mesh"
+//   file = "models/bunny.obj"
+//   material = "blue"
+//   scale = 1.0
+//   translate = [0, 0, 0]
+//
+// Every table is optional, the textures and materials are read in the order of the file.
+// Vectors are arrays of 3 numbers, colors may also be given by name (white, red...).
+// Color parameters of materials and textures also take the name of a texture defined above them.
+// The checker texture follows the texture coordinates, checker3d the position.
+// Noises : cell, perlin, fbm, turbulence and worley, scale is their frequency.
+// Principled roughness goes from 0 (mirror) to 1, conductors are gold, copper, aluminum and silver,
+// their base_color (white by default) tints the measured reflectance.
+// Dielectrics absorb along the way inside, transmittance being what is left after distance (1 by default).
+// A mix picks a or b at each hit, b with the probability amount (or the luminance of the mask texture).
+// Adaptive sampling first gives min_samples to every pixel, a quarter of sample_per_pixel by default and below it.
+// The environment map (exr, pfm, hdr...) replaces the background and is sampled as a light.
+// Mesh and environment files are relative to the scene file, their `usemtl` may name materials of the scene.
+
+pub struct Scene {
+    pub world: HittableList,
+    // emissive objects, also present in world, sampled for direct lighting
+    pub lights: HittableList,
+    pub settings: Settings,
+    // for the material id pass, the objects of world are numbered in the order of the file
+    pub material_ids: MaterialIds,
+}
+
+#[derive(Debug)]
+pub struct SceneError {
+    pub file: Option<String>,
+    pub line: usize,
+    pub message: String,
+}
+
+impl SceneError {
+    pub fn new(line: usize, message: String) -> SceneError {
+        SceneError { file: None, line, message }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), 0) => write!(f, "{}: {}", file, self.message),
+            (Some(file), line) => write!(f, "{}:{}: {}", file, line, self.message),
+            (None, line) => write!(f, "line {}: {}", line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+type SceneResult<T> = Result<T, SceneError>;
+
+// The file as written, the names and colors are resolved while building the scene
+// and the spans give the line of the errors found then
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    settings: Option<Spanned<SettingsDef>>,
+    environment: Option<Spanned<EnvironmentDef>>,
+    camera: Option<CameraDef>,
+    #[serde(default)]
+    textures: Named<Spanned<TextureDef>>,
+    #[serde(default)]
+    materials: Named<Spanned<MaterialDef>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDef>>,
+}
+
+// the options of the same name on the command line
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SettingsDef {
+    image_width: Option<u32>,
+    image_height: Option<u32>,
+    sample_per_pixel: Option<u32>,
+    progressive: Option<bool>,
+    pass_samples: Option<u32>,
+    adaptive: Option<bool>,
+    min_samples: Option<u32>,
+    sample_heat_map: Option<bool>,
+    time_budget: Option<Scalar>,
+    noise_threshold: Option<Scalar>,
+    write_interval: Option<Scalar>,
+    max_depth: Option<u32>,
+    roulette_diffuse: Option<u32>,
+    roulette_specular: Option<u32>,
+    roulette_transmission: Option<u32>,
+    seed: Option<u64>,
+    integrator: Option<String>,
+    ao_distance: Option<Scalar>,
+    sampler: Option<String>,
+    filter: Option<String>,
+    filter_radius: Option<Scalar>,
+    parallel: Option<bool>,
+    tile_size: Option<u32>,
+    tile_order: Option<String>,
+    light_sampling: Option<bool>,
+    aovs: Option<Vec<String>>,
+    aov_output: Option<String>,
+    denoise: Option<bool>,
+    keep_raw: Option<bool>,
+    tone_mapping: Option<String>,
+    exposure: Option<Scalar>,
+    // "sky" or a color
+    background: Option<ColorValue>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentDef {
+    file: String,
+    rotation: Option<Scalar>,
+    intensity: Option<Scalar>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDef {
+    look_from: [Scalar; 3],
+    look_at: [Scalar; 3],
+    vup: Option<[Scalar; 3]>,
+    vfov: Option<Scalar>,
+    aperture: Option<Scalar>,
+    focus_dist: Option<Scalar>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDef {
+    Solid {
+        color: ColorValue,
+    },
+    Checker {
+        even: ColorValue,
+        odd: ColorValue,
+        scale: Option<Scalar>,
+    },
+    #[serde(rename = "checker3d")]
+    Checker3D {
+        even: ColorValue,
+        odd: ColorValue,
+        scale: Option<Scalar>,
+    },
+    Image {
+        file: String,
+        wrap: Option<String>,
+    },
+    Noise {
+        noise: Option<String>,
+        color: Option<ColorValue>,
+        scale: Option<Scalar>,
+        octaves: Option<u32>,
+        lacunarity: Option<Scalar>,
+        gain: Option<Scalar>,
+    },
+    Marble {
+        base: ColorValue,
+        vein: ColorValue,
+        scale: Option<Scalar>,
+        turbulence: Option<Scalar>,
+        octaves: Option<u32>,
+        lacunarity: Option<Scalar>,
+        gain: Option<Scalar>,
+    },
+    Wood {
+        light: ColorValue,
+        dark: ColorValue,
+        rings: Option<Scalar>,
+        scale: Option<Scalar>,
+        turbulence: Option<Scalar>,
+        octaves: Option<u32>,
+        lacunarity: Option<Scalar>,
+        gain: Option<Scalar>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDef {
+    Lambertian {
+        albedo: ColorValue,
+    },
+    Metal {
+        albedo: ColorValue,
+        fuzz: Option<Scalar>,
+    },
+    Dielectric {
+        ir: Scalar,
+        tint: Option<ColorValue>,
+        absorption: Option<[Scalar; 3]>,
+        transmittance: Option<ColorValue>,
+        distance: Option<Scalar>,
+    },
+    Principled {
+        base_color: Option<ColorValue>,
+        metallic: Option<Scalar>,
+        roughness: Option<Scalar>,
+        ior: Option<Scalar>,
+        conductor: Option<String>,
+    },
+    CoatedDiffuse {
+        albedo: ColorValue,
+        ior: Option<Scalar>,
+        roughness: Option<Scalar>,
+    },
+    Mix {
+        a: String,
+        b: String,
+        amount: Option<Scalar>,
+        mask: Option<ColorValue>,
+    },
+    DiffuseLight {
+        emit: ColorValue,
+        intensity: Option<Scalar>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDef {
+    Sphere {
+        center: [Scalar; 3],
+        radius: Scalar,
+        material: String,
+    },
+    Quad {
+        origin: [Scalar; 3],
+        u: [Scalar; 3],
+        v: [Scalar; 3],
+        material: String,
+    },
+    Triangle {
+        a: [Scalar; 3],
+        b: [Scalar; 3],
+        c: [Scalar; 3],
+        material: String,
+    },
+    Mesh {
+        file: String,
+        material: String,
+        scale: Option<Scalar>,
+        translate: Option<[Scalar; 3]>,
+    },
+}
+
+// [r, g, b] or a name, of a color or of a texture of the scene
+enum ColorValue {
+    Rgb([Scalar; 3]),
+    Name(String),
+}
+
+impl<'de> Deserialize<'de> for ColorValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ColorValue, D::Error> {
+        struct ColorVisitor;
+
+        impl<'de> Visitor<'de> for ColorVisitor {
+            type Value = ColorValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a name or an array of length 3")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<ColorValue, E> {
+                Ok(ColorValue::Name(name.to_string()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<ColorValue, A::Error> {
+                <[Scalar; 3]>::deserialize(de::value::SeqAccessDeserializer::new(seq)).map(ColorValue::Rgb)
+            }
+        }
+
+        deserializer.deserialize_any(ColorVisitor)
+    }
+}
+
+// a table of definitions, kept in the order of the file as they may refer to the previous ones
+struct Named<T>(Vec<(String, T)>);
+
+impl<T> Default for Named<T> {
+    fn default() -> Named<T> {
+        Named(Vec::new())
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Named<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Named<T>, D::Error> {
+        struct NamedVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for NamedVisitor<T> {
+            type Value = Named<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a table of named definitions")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Named<T>, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(Named(entries))
+            }
+        }
+
+        deserializer.deserialize_map(NamedVisitor(PhantomData))
+    }
+}
+
+#[derive(Clone)]
+struct SceneMaterial {
+    mat: SP<dyn Material>,
+    emissive: bool,
+}
+
+fn line_of(source: &str, span: Range<usize>) -> usize {
+    source[..span.start].matches('\n').count() + 1
+}
+
+fn vec3(v: [Scalar; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+fn named_color(name: &str) -> Option<Color> {
+    match name {
+        "white" => Some(color::white()),
+        "black" => Some(color::black()),
+        "red" => Some(color::red()),
+        "green" => Some(color::green()),
+        "blue" => Some(color::blue()),
+        "yellow" => Some(color::yellow()),
+        "cyan" => Some(color::cyan()),
+        "magenta" => Some(color::magenta()),
+        _ => None,
+    }
+}
+
+fn color(line: usize, value: &ColorValue) -> SceneResult<Color> {
+    match value {
+        ColorValue::Rgb(c) => Ok(vec3(*c)),
+        ColorValue::Name(name) => named_color(name)
+            .ok_or_else(|| SceneError::new(line, format!("unknown color '{}'", name))),
+    }
+}
+
+// a texture of the scene by name, or a plain color
+fn texture(line: usize, value: &ColorValue, textures: &HashMap<String, SP<dyn Texture>>) -> SceneResult<SP<dyn Texture>> {
+    match value {
+        ColorValue::Name(name) if !textures.contains_key(name) && named_color(name).is_none() => {
+            Err(SceneError::new(line, format!("unknown texture or color '{}'", name)))
+        }
+        ColorValue::Name(name) if textures.contains_key(name) => Ok(textures[name].clone()),
+        _ => Ok(texture::solid(color(line, value)?)),
+    }
+}
+
+fn material(line: usize, name: &str, materials: &HashMap<String, SceneMaterial>) -> SceneResult<SceneMaterial> {
+    materials.get(name)
+        .cloned()
+        .ok_or_else(|| SceneError::new(line, format!("unknown material '{}'", name)))
+}
+
+// the names of the command line values
+fn value_enum<T: ValueEnum>(line: usize, what: &str, value: &str) -> SceneResult<T> {
+    T::from_str(value, true)
+        .map_err(|_| SceneError::new(line, format!("unknown {} '{}'", what, value)))
+}
+
+fn at_least(line: usize, key: &str, value: u32, min: u32) -> SceneResult<u32> {
+    if value < min {
+        return Err(SceneError::new(line, format!("{} must be at least {}", key, min)));
+    }
+    Ok(value)
+}
+
+fn positive(line: usize, key: &str, value: Scalar) -> SceneResult<Scalar> {
+    if value <= 0.0 {
+        return Err(SceneError::new(line, format!("{} must be positive", key)));
+    }
+    Ok(value)
+}
+
+// octaves, lacunarity and gain of the fractal noises
+fn fractal(line: usize, octaves: Option<u32>, lacunarity: Option<Scalar>, gain: Option<Scalar>) -> SceneResult<Fractal> {
+    let default = Fractal::default();
+    Ok(Fractal {
+        octaves: at_least(line, "octaves", octaves.unwrap_or(default.octaves), 1)?,
+        lacunarity: lacunarity.unwrap_or(default.lacunarity),
+        gain: gain.unwrap_or(default.gain),
+    })
+}
+
+fn parse_settings(def: &SettingsDef, line: usize, settings: &mut Settings) -> SceneResult<()> {
+    if let Some(w) = def.image_width {
+        settings.set_image_width(w);
+    }
+    if let Some(h) = def.image_height {
+        settings.image_height = h;
+    }
+    settings.check_image_size().map_err(|message| SceneError::new(line, message))?;
+
+    if let Some(spp) = def.sample_per_pixel {
+        settings.sample_per_pixel = at_least(line, "sample_per_pixel", spp, 1)?;
+    }
+    if let Some(progressive) = def.progressive {
+        settings.progressive = progressive;
+    }
+    if let Some(pass_samples) = def.pass_samples {
+        settings.pass_samples = at_least(line, "pass_samples", pass_samples, 1)?;
+    }
+    if let Some(adaptive) = def.adaptive {
+        settings.adaptive = adaptive;
+    }
+    if let Some(min_samples) = def.min_samples {
+        settings.min_samples = Some(at_least(line, "min_samples", min_samples, 2)?);
+    }
+    if let Some(heat_map) = def.sample_heat_map {
+        settings.sample_heat_map = heat_map;
+    }
+    if let Some(budget) = def.time_budget {
+        settings.time_budget = Some(positive(line, "time_budget", budget)?);
+    }
+    if let Some(threshold) = def.noise_threshold {
+        settings.noise_threshold = Some(positive(line, "noise_threshold", threshold)?);
+    }
+    if let Some(interval) = def.write_interval {
+        settings.write_interval = positive(line, "write_interval", interval)?;
+    }
+    if let Some(depth) = def.max_depth {
+        settings.max_depth = at_least(line, "max_depth", depth, 1)?;
+    }
+    if let Some(depth) = def.roulette_diffuse {
+        settings.russian_roulette.diffuse = depth;
+    }
+    if let Some(depth) = def.roulette_specular {
+        settings.russian_roulette.specular = depth;
+    }
+    if let Some(depth) = def.roulette_transmission {
+        settings.russian_roulette.transmission = depth;
+    }
+    if let Some(seed) = def.seed {
+        settings.seed = seed;
+    }
+    if let Some(value) = &def.integrator {
+        settings.integrator = value_enum::<IntegratorKind>(line, "integrator", value)?;
+    }
+    if let Some(distance) = def.ao_distance {
+        settings.ao_distance = positive(line, "ao_distance", distance)?;
+    }
+    if let Some(value) = &def.sampler {
+        settings.sampler = value_enum::<SamplerKind>(line, "sampler", value)?;
+    }
+    if let Some(value) = &def.filter {
+        settings.filter.kind = value_enum::<FilterKind>(line, "filter", value)?;
+    }
+    if let Some(radius) = def.filter_radius {
+        settings.filter.radius = Some(positive(line, "filter_radius", radius)?);
+    }
+    if let Some(parallel) = def.parallel {
+        settings.parallel = parallel;
+    }
+    if let Some(tile_size) = def.tile_size {
+        settings.tile_size = at_least(line, "tile_size", tile_size, 1)?;
+    }
+    if let Some(value) = &def.tile_order {
+        settings.tile_order = value_enum::<TileOrder>(line, "tile order", value)?;
+    }
+    if let Some(light_sampling) = def.light_sampling {
+        settings.light_sampling = light_sampling;
+    }
+    if let Some(names) = &def.aovs {
+        settings.aovs = names.iter()
+            .map(|name| value_enum::<Aov>(line, "aov", name))
+            .collect::<SceneResult<_>>()?;
+    }
+    if let Some(value) = &def.aov_output {
+        settings.aov_output = value_enum::<AovOutput>(line, "aov output", value)?;
+    }
+    if let Some(denoise) = def.denoise {
+        settings.denoise = denoise;
+    }
+    if let Some(keep_raw) = def.keep_raw {
+        settings.keep_raw = keep_raw;
+    }
+    if let Some(value) = &def.tone_mapping {
+        settings.tone_mapper.operator = value_enum::<ToneMapping>(line, "tone mapping", value)?;
+    }
+    if let Some(exposure) = def.exposure {
+        settings.tone_mapper.exposure = exposure;
+    }
+    if let Some(value) = &def.background {
+        settings.background = match value {
+            ColorValue::Name(name) if name == "sky" => Background::Sky,
+            _ => Background::Uniform(color(line, value)?),
+        };
+    }
+    Ok(())
+}
+
+fn parse_camera(def: &CameraDef) -> Camera {
+    let look_from = vec3(def.look_from);
+    let look_at = vec3(def.look_at);
+    let vup = def.vup.map_or(Vec3::new(0.0, 1.0, 0.0), vec3);
+    let vfov = def.vfov.unwrap_or(90.0);
+    let aperture = def.aperture.unwrap_or(0.0);
+    let focus_dist = def.focus_dist.unwrap_or_else(|| (look_from - look_at).length());
+
+    Camera::new(look_from, look_at, vup, vfov, aperture, focus_dist)
+}
+
+fn parse_texture(def: &TextureDef, line: usize, textures: &HashMap<String, SP<dyn Texture>>, base_dir: &Path) -> SceneResult<SP<dyn Texture>> {
+    let texture = |value| texture(line, value, textures);
+    let texture: SP<dyn Texture> = match def {
+        TextureDef::Solid { color } => texture(color)?,
+        TextureDef::Checker { even, odd, scale } => {
+            SP::new(Checker2D::new(texture(even)?, texture(odd)?, scale.unwrap_or(1.0)))
+        }
+        TextureDef::Checker3D { even, odd, scale } => {
+            SP::new(Checker3D::new(texture(even)?, texture(odd)?, scale.unwrap_or(1.0)))
+        }
+        TextureDef::Image { file, wrap } => {
+            let wrap = match wrap {
+                Some(value) => value_enum(line, "wrap mode", value)?,
+                None => WrapMode::Repeat,
+            };
+            let image = ImageTexture::load(&base_dir.join(file), wrap)
+                .map_err(|e| SceneError::new(line, e.to_string()))?;
+            SP::new(image)
+        }
+        TextureDef::Noise { noise, color: tint, scale, octaves, lacunarity, gain } => {
+            let noise = match noise {
+                Some(value) => value_enum(line, "noise", value)?,
+                None => NoiseKind::Perlin,
+            };
+            let tint = match tint {
+                Some(value) => color(line, value)?,
+                None => color::white(),
+            };
+            SP::new(NoiseTexture::new(noise, tint, scale.unwrap_or(1.0), fractal(line, *octaves, *lacunarity, *gain)?))
+        }
+        TextureDef::Marble { base, vein, scale, turbulence, octaves, lacunarity, gain } => {
+            let scale = scale.unwrap_or(1.0);
+            let turbulence = turbulence.unwrap_or(5.0);
+            SP::new(MarbleTexture::new(texture(base)?, texture(vein)?, scale, turbulence, fractal(line, *octaves, *lacunarity, *gain)?))
+        }
+        TextureDef::Wood { light, dark, rings, scale, turbulence, octaves, lacunarity, gain } => {
+            let rings = rings.unwrap_or(10.0);
+            let scale = scale.unwrap_or(1.0);
+            let turbulence = turbulence.unwrap_or(1.0);
+            SP::new(WoodTexture::new(texture(light)?, texture(dark)?, rings, scale, turbulence, fractal(line, *octaves, *lacunarity, *gain)?))
+        }
+    };
+    Ok(texture)
+}
+
+fn parse_material(def: &MaterialDef, line: usize, textures: &HashMap<String, SP<dyn Texture>>, materials: &HashMap<String, SceneMaterial>) -> SceneResult<SceneMaterial> {
+    let texture = |value| texture(line, value, textures);
+    let mat: SP<dyn Material> = match def {
+        MaterialDef::Lambertian { albedo } => SP::new(Lambertian::new(texture(albedo)?)),
+        MaterialDef::Metal { albedo, fuzz } => SP::new(Metal::new(texture(albedo)?, fuzz.unwrap_or(0.0))),
+        MaterialDef::Dielectric { ir, tint, absorption, transmittance, distance } => {
+            let tint = match tint {
+                Some(value) => texture(value)?,
+                None => texture::solid(color::white()),
+            };
+            let absorption = match (absorption, transmittance) {
+                (Some(_), Some(_)) => return Err(SceneError::new(line, "give either absorption or transmittance".to_string())),
+                (Some(absorption), None) => vec3(*absorption),
+                (None, Some(transmittance)) => Dielectric::absorption(color(line, transmittance)?, distance.unwrap_or(1.0)),
+                (None, None) => color::black(),
+            };
+            SP::new(Dielectric::new(*ir, tint, absorption))
+        }
+        MaterialDef::Principled { base_color, metallic, roughness, ior, conductor } => {
+            let conductor = match conductor {
+                Some(value) => Some(value_enum::<Conductor>(line, "conductor", value)?),
+                None => None,
+            };
+            let base_color = match (base_color, conductor) {
+                (Some(value), _) => texture(value)?,
+                // the measured reflectance, untinted
+                (None, Some(_)) => texture::solid(color::white()),
+                (None, None) => return Err(SceneError::new(line, "missing field `base_color`".to_string())),
+            };
+            let metallic = metallic.unwrap_or(0.0);
+            let roughness = roughness.unwrap_or(0.5);
+            let ior = ior.unwrap_or(1.5);
+            SP::new(Principled::new(base_color, metallic, roughness, ior, conductor))
+        }
+        MaterialDef::CoatedDiffuse { albedo, ior, roughness } => {
+            SP::new(CoatedDiffuse::new(texture(albedo)?, ior.unwrap_or(1.5), roughness.unwrap_or(0.0)))
+        }
+        MaterialDef::Mix { a, b, amount, mask } => {
+            let a = material(line, a, materials)?;
+            let b = material(line, b, materials)?;
+            let amount = match (amount, mask) {
+                (Some(_), Some(_)) => return Err(SceneError::new(line, "give either amount or mask".to_string())),
+                (Some(amount), None) => texture::solid(Color::uni(*amount)),
+                (None, Some(mask)) => texture(mask)?,
+                (None, None) => texture::solid(Color::uni(0.5)),
+            };
+            let emissive = a.emissive || b.emissive;
+            return Ok(SceneMaterial { mat: SP::new(MixMaterial::new(a.mat, b.mat, amount)), emissive });
+        }
+        MaterialDef::DiffuseLight { emit, intensity } => {
+            let mat = SP::new(DiffuseLight::new(texture(emit)?, intensity.unwrap_or(1.0)));
+            return Ok(SceneMaterial { mat, emissive: true });
+        }
+    };
+    Ok(SceneMaterial { mat, emissive: false })
+}
+
+// numbered from 1 for the object id pass
+fn add_object(world: &mut HittableList, object: Box<dyn Hittable>) {
+    let id = world.len() as u32 + 1;
+    world.add(Box::new(Tagged::new(object, id)));
+}
+
+// relative paths are resolved from base_dir
+pub fn parse_scene(source: &str, base_dir: &Path) -> SceneResult<Scene> {
+    let line = |span: Range<usize>| line_of(source, span);
+    let file: SceneFile = toml::from_str(source)
+        .map_err(|e| SceneError::new(e.span().map_or(0, line), e.message().to_string()))?;
+
+    let mut settings: Settings = Default::default();
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+    let mut materials: HashMap<String, SceneMaterial> = HashMap::new();
+    let mut textures: HashMap<String, SP<dyn Texture>> = HashMap::new();
+    let mut material_ids = MaterialIds::default();
+
+    // the settings are checked as a whole once the file is read
+    let settings_line = file.settings.as_ref().map_or(0, |def| line(def.span()));
+    if let Some(def) = &file.settings {
+        parse_settings(def.get_ref(), settings_line, &mut settings)?;
+    }
+    if let Some(def) = &file.environment {
+        let env = def.get_ref();
+        let rotation = env.rotation.unwrap_or(0.0);
+        let intensity = env.intensity.unwrap_or(1.0);
+        let env_map = EnvMap::load(&base_dir.join(&env.file), rotation, intensity)
+            .map_err(|e| SceneError::new(line(def.span()), e.to_string()))?;
+        settings.background = Background::EnvMap(SP::new(env_map));
+    }
+    if let Some(def) = &file.camera {
+        settings.camera = parse_camera(def);
+    }
+
+    for (name, def) in &file.textures.0 {
+        let texture = parse_texture(def.get_ref(), line(def.span()), &textures, base_dir)?;
+        textures.insert(name.clone(), texture);
+    }
+    for (name, def) in &file.materials.0 {
+        let mat = parse_material(def.get_ref(), line(def.span()), &textures, &materials)?;
+        material_ids.register(&mat.mat);
+        materials.insert(name.clone(), mat);
+    }
+
+    for def in &file.objects {
+        let line = line(def.span());
+        match def.get_ref() {
+            ObjectDef::Sphere { center, radius, material: name } => {
+                let mat = material(line, name, &materials)?;
+                let sphere = Sphere::new(vec3(*center), *radius, mat.mat);
+                if mat.emissive {
+                    lights.add(Box::new(sphere.clone()));
+                }
+                add_object(&mut world, Box::new(sphere));
+            }
+            ObjectDef::Quad { origin, u, v, material: name } => {
+                let mat = material(line, name, &materials)?;
+                let quad = Quad::new(vec3(*origin), vec3(*u), vec3(*v), mat.mat);
+                if mat.emissive {
+                    lights.add(Box::new(quad.clone()));
+                }
+                add_object(&mut world, Box::new(quad));
+            }
+            ObjectDef::Triangle { a, b, c, material: name } => {
+                let mat = material(line, name, &materials)?;
+                let triangle = Triangle::new(vec3(*a), vec3(*b), vec3(*c), mat.mat);
+                if mat.emissive {
+                    lights.add(Box::new(triangle.clone()));
+                }
+                add_object(&mut world, Box::new(triangle));
+            }
+            ObjectDef::Mesh { file, material: name, scale, translate } => {
+                let mat = material(line, name, &materials)?;
+                let scale = scale.unwrap_or(1.0);
+                let translate: Point3 = translate.map(vec3).unwrap_or_default();
+                let scene_materials: HashMap<String, SP<dyn Material>> = materials.iter()
+                    .map(|(name, m)| (name.clone(), m.mat.clone()))
+                    .collect();
+                // emissive meshes are not sampled as lights, they are only found by bsdf sampling
+                let mesh = obj::load_obj(&base_dir.join(file), &scene_materials, mat.mat, scale, translate)
+                    .map_err(|e| SceneError::new(line, e.to_string()))?;
+                println!("Mesh {} : {} triangles", file, mesh.nb_triangles());
+                for mat in mesh.materials() {
+                    material_ids.register(mat);
+                }
+                add_object(&mut world, Box::new(mesh));
+            }
+        }
+    }
+
+    settings.check_min_samples().map_err(|message| SceneError::new(settings_line, message))?;
+    // the camera may come before or after the image size
+    settings.fit_camera();
+    Ok(Scene { world, lights, settings, material_ids })
+}
+
+pub fn load_scene(path: &str) -> SceneResult<Scene> {
+    let with_file = |mut e: SceneError| {
+        e.file = Some(path.to_string());
+        e
+    };
+    let source = fs::read_to_string(path)
+        .map_err(|e| with_file(SceneError::new(0, format!("cannot read scene: {}", e))))?;
+    let base_dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    parse_scene(&source, base_dir).map_err(with_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Scene {
+        match parse_scene(source, Path::new("")) {
+            Ok(scene) => scene,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn error(source: &str) -> SceneError {
+        match parse_scene(source, Path::new("")) {
+            Ok(_) => panic!("parsed {:?}", source),
+            Err(e) => e,
+        }
+    }
+
+    const SPHERE: &str = r#"
+[materials]
+red = { type = "lambertian", albedo = "red" }
+
+[[objects]]
+type = "sphere"
+center = [0, 0, -1]
+radius = 0.5
+material = "red"
+"#;
+
+    #[test]
+    fn objects_lights_and_settings() {
+        let scene = parse(r#"
+            # comment
+            [settings]
+            image_width = 200
+            sample_per_pixel = 4
+            max_depth = 3
+
+            [camera]
+            look_from = [0, 0, 1]
+            look_at = [0, 0, -1]
+            vfov = 40
+
+            [materials]
+            red = { type = "lambertian", albedo = "red" }   # trailing comment
+            lamp = { type = "diffuse_light", emit = [1, 1, 0.5], intensity = 2 }
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, -1]
+            radius = 0.5
+            material = "red"
+
+            [[objects]]
+            type = "quad"
+            origin = [-1, 2, -2]
+            u = [2, 0, 0]
+            v = [0, 0, 2]
+            material = "lamp"
+
+            [[objects]]
+            type = "triangle"
+            a = [0, 0, 0]
+            b = [1, 0, 0]
+            c = [0, 1, 0]
+            material = "red"
+        "#);
+        assert_eq!(scene.world.len(), 3);
+        assert_eq!(scene.lights.len(), 1);
+        assert_eq!(scene.settings.image_width, 200);
+        assert_eq!(scene.settings.image_height, 112);
+        assert_eq!(scene.settings.sample_per_pixel, 4);
+        assert_eq!(scene.settings.max_depth, 3);
+        assert_eq!(scene.settings.camera.vfov, 40.0);
+    }
+
+    #[test]
+    fn documented_settings() {
+        let source: String = include_str!("scene.rs").lines()
+            .filter_map(|line| line.strip_prefix("//"))
+            .map(str::trim_start)
+            .skip_while(|line| *line != "[settings]")
+            .take_while(|line| !line.is_empty())
+            .map(|line| format!("{}\n", line))
+            .collect();
+        assert_eq!(source.lines().count(), 32);
+        let scene = parse(&source);
+        assert_eq!(scene.settings.min_samples(), 4);
+        assert!(scene.settings.denoise);
+    }
+
+    #[test]
+    fn camera_fits_the_image() {
+        // the size is given after the camera
+        let scene = parse("
+            [camera]
+            look_from = [0, 0, 1]
+            look_at = [0, 0, -1]
+
+            [settings]
+            image_width = 160
+            image_height = 160
+        ");
+        assert_eq!(scene.settings.camera.aspect_ratio, 1.0);
+    }
+
+    #[test]
+    fn adaptive_min_samples() {
+        let scene = parse("settings = { sample_per_pixel = 64, adaptive = true }");
+        assert_eq!(scene.settings.min_samples(), 16);
+        let scene = parse("settings = { sample_per_pixel = 64, adaptive = true, min_samples = 8 }");
+        assert_eq!(scene.settings.min_samples(), 8);
+        let e = error("settings = { adaptive = true, min_samples = 16 }");
+        assert_eq!(e.message, "min_samples (16) must be below sample_per_pixel (16)");
+        let e = error("settings = { sample_per_pixel = 2, adaptive = true }");
+        assert_eq!(e.message, "min_samples (2) must be below sample_per_pixel (2)");
+        // whatever the order of the keys
+        let scene = parse("[settings]\nadaptive = true\nmin_samples = 20\nsample_per_pixel = 64");
+        assert_eq!(scene.settings.min_samples(), 20);
+        let e = error("# adaptive\n\n[settings]\nadaptive = true\nmin_samples = 20");
+        assert_eq!((e.line, e.message.as_str()), (3, "min_samples (20) must be below sample_per_pixel (16)"));
+    }
+
+    #[test]
+    fn filter_radius() {
+        let scene = parse(r#"settings = { filter = "lanczos" }"#);
+        assert_eq!(scene.settings.filter.radius(), 3.0);
+        // whatever the order of the keys
+        let scene = parse(r#"settings = { filter_radius = 2, filter = "lanczos" }"#);
+        assert_eq!(scene.settings.filter.radius(), 2.0);
+        let e = error("settings = { filter_radius = 0 }");
+        assert_eq!(e.message, "filter_radius must be positive");
+        let e = error(r#"settings = { filter = "blur" }"#);
+        assert!(e.message.starts_with("unknown filter 'blur'"), "{}", e.message);
+    }
+
+    #[test]
+    fn unknown_material() {
+        let e = error(&SPHERE.replace(r#"material = "red""#, r#"material = "blue""#));
+        assert_eq!(e.line, 5);
+        assert_eq!(e.message, "unknown material 'blue'");
+        assert_eq!(e.to_string(), "line 5: unknown material 'blue'");
+        let e = error("[materials]\nworn = { type = \"mix\", a = \"chrome\", b = \"red\" }");
+        assert_eq!((e.line, e.message.as_str()), (2, "unknown material 'chrome'"));
+    }
+
+    #[test]
+    fn unknown_types() {
+        let e = error("\n[materials]\nred = { type = \"plastic\", albedo = \"red\" }");
+        assert_eq!(e.line, 3);
+        assert!(e.message.starts_with("unknown variant `plastic`"), "{}", e.message);
+        let e = error("[textures]\ncubes = { type = \"checker4d\", even = \"red\", odd = \"white\" }");
+        assert!(e.message.starts_with("unknown variant `checker4d`"), "{}", e.message);
+        let e = error("[[objects]]\ntype = \"cube\"");
+        assert!(e.message.starts_with("unknown variant `cube`"), "{}", e.message);
+    }
+
+    #[test]
+    fn colors_and_textures() {
+        let scene = parse(r#"
+            [textures]
+            tiles = { type = "checker", even = "white", odd = [0.2, 0.2, 0.2] }
+            [materials]
+            floor = { type = "lambertian", albedo = "tiles" }
+            plastic = { type = "principled", base_color = "red" }
+        "#);
+        assert!(scene.world.is_empty());
+        let e = error("[materials]\nfloor = { type = \"lambertian\", albedo = \"tiles\" }");
+        assert_eq!((e.line, e.message.as_str()), (2, "unknown texture or color 'tiles'"));
+        let e = error("[textures]\nclouds = { type = \"noise\", color = \"tiles\" }");
+        assert_eq!(e.message, "unknown color 'tiles'");
+        let e = error(r#"settings = { background = "night" }"#);
+        assert_eq!(e.message, "unknown color 'night'");
+        let e = error("[materials]\nink = { type = \"dielectric\", ir = 1.5, absorption = [1, 1, 1], transmittance = \"red\" }");
+        assert_eq!(e.message, "give either absorption or transmittance");
+    }
+
+    #[test]
+    fn malformed_vectors() {
+        for vector in ["[0, 0]", "[0, 0, -1, 2]", r#"[0, "x", -1]"#, "[]", r#""0,0,-1""#] {
+            // the errors of a table of the array are on its header
+            let e = error(&SPHERE.replace("[0, 0, -1]", vector));
+            assert_eq!(e.line, 5, "{}", vector);
+        }
+        let e = error(&SPHERE.replace("[0, 0, -1]", "[0, 0]"));
+        assert_eq!(e.message, "invalid length 2, expected an array of length 3");
+        let e = error("[materials]\nred = { type = \"lambertian\", albedo = [1, 0] }");
+        assert_eq!(e.line, 2);
+        assert_eq!(e.message, "invalid length 2, expected an array of length 3");
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        let e = error(&SPHERE.replace("0.5", r#""big""#));
+        assert_eq!(e.line, 5);
+        assert!(e.message.contains(r#""big""#), "{}", e.message);
+        let e = error("[settings]\nsample_per_pixel = -3");
+        assert_eq!((e.line, e.message.as_str()), (2, "invalid value: integer `-3`, expected u32"));
+    }
+
+    #[test]
+    fn parameters_are_checked() {
+        let e = error(&format!("{}color = \"blue\"\n", SPHERE));
+        assert_eq!(e.line, 5);
+        assert!(e.message.starts_with("unknown field `color`"), "{}", e.message);
+        let e = error("[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, -1]\nradius = 0.5");
+        assert_eq!((e.line, e.message.as_str()), (1, "missing field `material`"));
+        let e = error("[settings]\nseed = 1\nseed = 2");
+        assert!(e.message.starts_with("duplicate key `seed`"), "{}", e.message);
+        for key in ["time_budget", "noise_threshold", "write_interval"] {
+            let e = error(&format!("[settings]\n{} = 0", key));
+            assert_eq!((e.line, e.message), (1, format!("{} must be positive", key)));
+        }
+        let e = error("[settings]\nsample_per_pixel = 0");
+        assert_eq!(e.message, "sample_per_pixel must be at least 1");
+    }
+
+    #[test]
+    fn names_and_tables() {
+        let e = error("[materials]\nred = { type = \"lambertian\", albedo = \"red\" }\nred = { type = \"metal\", albedo = \"red\" }");
+        assert_eq!(e.line, 3);
+        assert!(e.message.starts_with("duplicate key `red`"), "{}", e.message);
+        let e = error("\n\n[cubes]\ncenter = [0, 0, 0]");
+        assert_eq!(e.line, 3);
+        assert!(e.message.starts_with("unknown field `cubes`"), "{}", e.message);
+    }
+}
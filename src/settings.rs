@@ -1,51 +1,168 @@
+use clap::ValueEnum;
+
+use crate::aov::{Aov, AovOutput};
 use crate::camera::Camera;
-use crate::vec3::{Point3, Vec3};
-use crate::common;
+use crate::color::{Color, ToneMapper};
+use crate::framebuffer::ImageFormat;
+use crate::vec3::{Point3, Scalar, Vec3};
+use crate::common::SP;
+use crate::env_map::EnvMap;
+use crate::tile::TileOrder;
+use crate::filter::Filter;
+use crate::integrator::IntegratorKind;
+use crate::material::Lobe;
+use crate::sampler::SamplerKind;
+
+// what a ray escaping the scene sees
+#[derive(Clone)]
+pub enum Background {
+    Sky,
+    Uniform(Color),
+    // also sampled for direct lighting
+    EnvMap(SP<EnvMap>),
+}
+
+// Depth from which the paths leaving each kind of bounce may be stopped,
+// the ones which go on are weighted up to keep the estimate unbiased
+#[derive(Copy, Clone)]
+pub struct RussianRoulette {
+    pub diffuse: u32,
+    pub specular: u32,
+    pub transmission: u32,
+}
 
-use exr::prelude::*;
-use exr::image::pixel_vec::*; // import predefined pixel storage
+impl RussianRoulette {
+    pub fn min_depth(&self, lobe: Lobe) -> u32 {
+        match lobe {
+            Lobe::Diffuse => self.diffuse,
+            Lobe::Glossy | Lobe::Specular => self.specular,
+            Lobe::Transmission => self.transmission,
+        }
+    }
+}
 
-type DynamicRgbaPixel = (Sample, Sample, Sample, Sample); // `Sample` is an enum containing the original data type (f16,f32, or u32)
-pub type PixelImg = PixelImage<PixelVec<DynamicRgbaPixel>, RgbaChannels> ;
+impl Default for RussianRoulette {
+    // caustics and glass need longer paths than the diffuse interreflections
+    fn default() -> Self {
+        RussianRoulette { diffuse: 3, specular: 5, transmission: 8 }
+    }
+}
 
+#[derive(Clone)]
 pub struct Settings {
     pub image_width: u32,
     pub image_height: u32,
     pub parallel: bool,
+    // square buckets of pixels, the unit of work of the render threads
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
 
+    // the sample budget of progressive renders
     pub sample_per_pixel: u32,
+    // passes of pass_samples samples per pixel, stopped early by the time budget (seconds)
+    // or when the relative error of every pixel is under the noise threshold
+    pub progressive: bool,
+    pub pass_samples: u32,
+    // progressive, min_samples everywhere then the passes go to the noisiest pixels.
+    // A quarter of the budget unless given
+    pub adaptive: bool,
+    pub min_samples: Option<u32>,
+    // debug image of the number of samples of every pixel
+    pub sample_heat_map: bool,
+    pub time_budget: Option<f64>,
+    pub noise_threshold: Option<f64>,
+    // seconds between two writes of the image so far
+    pub write_interval: f64,
+    // hard limit on the number of bounces, the paths are usually stopped earlier by the roulette
     pub max_depth: u32,
+    pub russian_roulette: RussianRoulette,
+    // next event estimation on the emissive objects
+    pub light_sampling: bool,
+    // the same seed gives the same image, sequential or parallel
+    pub seed: u64,
+    // how the dimensions of the samples (pixel, lens, bounces) are distributed
+    pub sampler: SamplerKind,
+    // pixel reconstruction, the samples are splatted to the pixels within the filter radius
+    pub filter: Filter,
+
+    // path tracing, or a debug view of the first hits
+    pub integrator: IntegratorKind,
+    // reach of the occlusion rays of the ambient occlusion integrator
+    pub ao_distance: Scalar,
 
     pub camera: Camera,
+    pub background: Background,
 
-    pub env_map: Option<common::SP<PixelImg>>,
+    pub output_dir: String,
+    pub output_formats: Vec<ImageFormat>,
+    pub tone_mapper: ToneMapper,
+    // first hit passes written along the image
+    pub aovs: Vec<Aov>,
+    pub aov_output: AovOutput,
+    // filtering guided by the albedo and normal passes, keep_raw also writes the image before it
+    pub denoise: bool,
+    pub keep_raw: bool,
 }
 
 impl Settings {
-    pub fn dump(&self) {
-        println!("= Settings");
-        println!("=== Execution {}", if self.parallel {"parallel"} else {"sequential"});
-        println!("=== vfov {} degrees", self.camera.vfov);
-        println!("========================================================");
+    // keeps the camera aspect ratio
+    pub fn set_image_width(&mut self, image_width: u32) {
+        self.image_width = image_width;
+        self.image_height = ((image_width as f64) / self.camera.aspect_ratio) as u32;
+    }
+
+    // the viewport takes the shape of the image, whatever sizes were given
+    pub fn fit_camera(&mut self) {
+        self.camera.set_aspect_ratio(self.image_width as f64 / self.image_height as f64);
     }
-}
 
-fn load_exr(path: &str) -> PixelImg {
+    pub fn min_samples(&self) -> u32 {
+        self.min_samples.unwrap_or((self.sample_per_pixel / 4).max(2))
+    }
 
-    // load an rgba image
-    // this specific example discards all but the first valid rgb layers and converts all pixels to f32 values
-    // TODO optional alpha channel!
-    let image: PixelImg = read_first_rgba_layer_from_file(
-        path,
-        PixelVec::<DynamicRgbaPixel>::constructor,
+    // the camera rays are spread over width - 1 and height - 1 pixels
+    pub fn check_image_size(&self) -> Result<(), String> {
+        if self.image_width < 2 || self.image_height < 2 {
+            return Err(format!("image must be at least 2x2 pixels, not {}x{}", self.image_width, self.image_height));
+        }
+        Ok(())
+    }
 
-        // use this predefined rgba pixel container from the exr crate, requesting any type of pixels with 3 or 4 values
-        PixelVec::set_pixel
-    ).expect("Cannot find file ? ");
+    // the adaptive passes need samples left once every pixel has min_samples
+    pub fn check_min_samples(&self) -> Result<(), String> {
+        if (self.adaptive || self.min_samples.is_some()) && self.min_samples() >= self.sample_per_pixel {
+            return Err(format!("min_samples ({}) must be below sample_per_pixel ({})", self.min_samples(), self.sample_per_pixel));
+        }
+        Ok(())
+    }
 
-    //println!("w {:?} ", );
-    println!("pixel {:?} ", image.layer_data.channel_data.pixels.get_pixel(Vec2(1, 0)));
-    return image;
+    pub fn dump(&self) {
+        println!("= Settings");
+        println!("=== Execution {}, tiles of {} pixels", if self.parallel {"parallel"} else {"sequential"}, self.tile_size);
+        println!("=== Image {}x{}", self.image_width, self.image_height);
+        println!("=== {} samples per pixel, max depth {}", self.sample_per_pixel, self.max_depth);
+        println!("=== Russian roulette after {} diffuse, {} specular, {} transmission bounces",
+            self.russian_roulette.diffuse, self.russian_roulette.specular, self.russian_roulette.transmission);
+        if self.progressive || self.adaptive {
+            println!("=== Progressive, {} samples per pass", self.pass_samples);
+            if self.adaptive {
+                println!("=== Adaptive, at least {} samples per pixel", self.min_samples());
+            }
+            if let Some(budget) = self.time_budget {
+                println!("=== Time budget {}s", budget);
+            }
+            if let Some(threshold) = self.noise_threshold {
+                println!("=== Noise threshold {}", threshold);
+            }
+        }
+        if self.integrator != IntegratorKind::Path {
+            println!("=== {} integrator", self.integrator.to_possible_value().unwrap().get_name());
+        }
+        println!("=== Seed {}, {} sampler", self.seed, self.sampler.to_possible_value().unwrap().get_name());
+        println!("=== {} filter, radius {} pixels", self.filter.kind.to_possible_value().unwrap().get_name(), self.filter.radius());
+        println!("=== vfov {} degrees", self.camera.vfov);
+        println!("========================================================");
+    }
 }
 
 impl Default for Settings {
@@ -66,19 +183,38 @@ impl Default for Settings {
             dist_to_focus);
         let image_width = 400;
         let image_height = ((image_width as f64) / camera.aspect_ratio) as u32;
-        let env_map_filename = "ressources\\hdri\\symmetrical_garden_02_4k.exr";
-        //let env_map_filename = "./ressources/hdri/kloofendal_48d_partly_cloudy_puresky_4k.exr";
-        //let env_map_filename = "./ressources/hdri/studio_small_03_4k.exr";
-        
         Self {
-            image_width: image_width,
-            image_height: image_height,
+            image_width,
+            image_height,
             parallel: true,
-            camera: camera,
+            tile_size: 32,
+            tile_order: TileOrder::Spiral,
+            integrator: IntegratorKind::Path,
+            ao_distance: 1.0,
+            camera,
+            background: Background::Sky,
             sample_per_pixel: 16,
+            progressive: false,
+            pass_samples: 4,
+            adaptive: false,
+            min_samples: None,
+            sample_heat_map: false,
+            time_budget: None,
+            noise_threshold: None,
+            write_interval: 10.0,
             max_depth: 10,
-            //env_map: common::SP::new(load_exr(&env_map_filename)),
-            env_map: None,
+            russian_roulette: Default::default(),
+            light_sampling: true,
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            filter: Default::default(),
+            output_dir: "output".to_string(),
+            output_formats: vec![ImageFormat::Png],
+            tone_mapper: Default::default(),
+            aovs: Vec::new(),
+            aov_output: AovOutput::Separate,
+            denoise: false,
+            keep_raw: false,
         }
     }
 }
\ No newline at end of file
@@ -1,23 +1,39 @@
+use crate::aabb::Aabb;
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
-use crate::common::SP;
+use crate::common::{self, SP};
+use crate::sampler::Sampler;
+use crate::onb::Onb;
 use crate::ray::Ray;
 use crate::vec3::{self, Point3, Vec3, Scalar};
 
+#[derive(Clone)]
 pub struct Sphere {
     center: Point3,
     radius: Scalar,
     mat: SP<dyn Material>,
+    bbox: Aabb,
 }
 
 impl Sphere {
     pub fn new(center: Point3, radius: Scalar, mat: SP<dyn Material>) -> Sphere {
+        // negative radius spheres (hollow glass) have the same bounds
+        let r = Vec3::uni(radius.abs());
         Sphere{
             center,
             radius,
             mat,
+            bbox: Aabb::new(center - r, center + r),
         }
     }
+
+    // u around the y axis from -x, v from the bottom pole to the top one, both in [0, 1]
+    fn uv(n: Vec3) -> Vec3 {
+        let theta = Scalar::acos((-n.y()).clamp(-1.0, 1.0));
+        // atan2 is 0 rather than NaN at the poles
+        let phi = Scalar::atan2(-n.z(), n.x()) + common::PI;
+        Vec3::new(phi / (2.0 * common::PI), theta / common::PI, 0.0)
+    }
 }
 
 impl Hittable for Sphere {
@@ -47,9 +63,46 @@ impl Hittable for Sphere {
         let outward_normal = (rec.p - self.center) / self.radius;
         rec.set_face_normal(ray, outward_normal);
         rec.mat = Some(self.mat.clone());
-        let spherical = vec3::to_spherical(rec.normal);
-        rec.uv = Vec3::new(spherical.y(), spherical.z(), 0.0);
+        rec.uv = Self::uv((rec.p - self.center) / self.radius.abs());
 
         true
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // directions are sampled uniformly in the cone subtended by the sphere
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> Scalar {
+        let mut rec = HitRecord::new();
+        if !self.hit(&Ray::new(origin, direction), 0.001, common::INFINITY, &mut rec) {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return 0.0;
+        }
+        let cos_theta_max = Scalar::sqrt(1.0 - radius_squared / distance_squared);
+        let solid_angle = 2.0 * common::PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    fn random_direction(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let direction = self.center - origin;
+        let distance_squared = direction.length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return direction;
+        }
+
+        let (r1, r2) = sampler.get_2d();
+        let z = 1.0 + r2 * (Scalar::sqrt(1.0 - radius_squared / distance_squared) - 1.0);
+        let phi = 2.0 * common::PI * r1;
+        let sin_theta = Scalar::sqrt(1.0 - z * z);
+        let local = Vec3::new(Scalar::cos(phi) * sin_theta, Scalar::sin(phi) * sin_theta, z);
+
+        Onb::new(direction).local(local)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,223 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use crate::color::Color;
+use crate::common::{self, SP};
+use crate::hittable::HitRecord;
+use crate::image_io::{self, ImageError};
+use crate::noise::{Fractal, NoiseKind, Perlin};
+use crate::vec3::Scalar;
+
+// Color varying over a surface, looked up from the texture coordinates or the position of the hit
+pub trait Texture: Send + Sync {
+    fn value(&self, rec: &HitRecord) -> Color;
+}
+
+pub struct SolidColor {
+    color: Color,
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _rec: &HitRecord) -> Color {
+        self.color
+    }
+}
+
+pub fn solid(color: Color) -> SP<dyn Texture> {
+    SP::new(SolidColor { color })
+}
+
+// scale squares per unit of texture coordinates
+pub struct Checker2D {
+    even: SP<dyn Texture>,
+    odd: SP<dyn Texture>,
+    scale: Scalar,
+}
+
+impl Checker2D {
+    pub fn new(even: SP<dyn Texture>, odd: SP<dyn Texture>, scale: Scalar) -> Checker2D {
+        Checker2D { even, odd, scale }
+    }
+}
+
+impl Texture for Checker2D {
+    fn value(&self, rec: &HitRecord) -> Color {
+        let cell = (self.scale * rec.uv.x()).floor() as i64 + (self.scale * rec.uv.y()).floor() as i64;
+        if cell.rem_euclid(2) == 0 { self.even.value(rec) } else { self.odd.value(rec) }
+    }
+}
+
+// scale cubes per unit of world space, independent of the texture coordinates
+pub struct Checker3D {
+    even: SP<dyn Texture>,
+    odd: SP<dyn Texture>,
+    scale: Scalar,
+}
+
+impl Checker3D {
+    pub fn new(even: SP<dyn Texture>, odd: SP<dyn Texture>, scale: Scalar) -> Checker3D {
+        Checker3D { even, odd, scale }
+    }
+}
+
+impl Texture for Checker3D {
+    fn value(&self, rec: &HitRecord) -> Color {
+        let cell: i64 = rec.p.e.iter().map(|c| (self.scale * c).floor() as i64).sum();
+        if cell.rem_euclid(2) == 0 { self.even.value(rec) } else { self.odd.value(rec) }
+    }
+}
+
+// What the texture coordinates outside [0, 1] read
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum WrapMode {
+    Repeat,
+    // the border texels
+    Clamp,
+    // every other repetition flipped
+    Mirror,
+}
+
+impl WrapMode {
+    fn apply(&self, i: isize, size: usize) -> usize {
+        let n = size as isize;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Clamp => i.clamp(0, n - 1),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * n);
+                if i < n { i } else { 2 * n - 1 - i }
+            }
+        };
+        i as usize
+    }
+}
+
+// Bilinearly filtered picture, v = 0 at the bottom row
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    wrap: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn load(path: &Path, wrap: WrapMode) -> Result<ImageTexture, ImageError> {
+        let (width, height, pixels) = image_io::load_pixels(path)?;
+        Ok(ImageTexture { width, height, pixels, wrap })
+    }
+
+    fn texel(&self, x: isize, y: isize) -> Color {
+        let x = self.wrap.apply(x, self.width);
+        let y = self.wrap.apply(y, self.height);
+        self.pixels[y * self.width + x]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        let x = rec.uv.x() * self.width as Scalar - 0.5;
+        let y = (1.0 - rec.uv.y()) * self.height as Scalar - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let (x0, y0) = (x0 as isize, y0 as isize);
+
+        let top = (1.0 - fx) * self.texel(x0, y0) + fx * self.texel(x0 + 1, y0);
+        let bottom = (1.0 - fx) * self.texel(x0, y0 + 1) + fx * self.texel(x0 + 1, y0 + 1);
+        (1.0 - fy) * top + fy * bottom
+    }
+}
+
+// part of the scene, the same whatever the render seed
+fn scene_perlin() -> Perlin {
+    Perlin::new(&mut common::seeded_rng(0))
+}
+
+fn mix(a: Color, b: Color, t: Scalar) -> Color {
+    (1.0 - t) * a + t * b
+}
+
+// color modulated by a noise of the position, scale being the frequency of the noise
+pub struct NoiseTexture {
+    kind: NoiseKind,
+    color: Color,
+    scale: Scalar,
+    fractal: Fractal,
+    perlin: Perlin,
+}
+
+impl NoiseTexture {
+    pub fn new(kind: NoiseKind, color: Color, scale: Scalar, fractal: Fractal) -> NoiseTexture {
+        NoiseTexture {
+            kind,
+            color,
+            scale,
+            fractal,
+            perlin: scene_perlin(),
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        self.perlin.value(self.kind, self.scale * rec.p, &self.fractal) * self.color
+    }
+}
+
+// Veins along z, bent by turbulence
+pub struct MarbleTexture {
+    base: SP<dyn Texture>,
+    vein: SP<dyn Texture>,
+    scale: Scalar,
+    // how far the veins are pushed around
+    turbulence: Scalar,
+    fractal: Fractal,
+    perlin: Perlin,
+}
+
+impl MarbleTexture {
+    pub fn new(base: SP<dyn Texture>, vein: SP<dyn Texture>, scale: Scalar, turbulence: Scalar, fractal: Fractal) -> MarbleTexture {
+        MarbleTexture { base, vein, scale, turbulence, fractal, perlin: scene_perlin() }
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        let p = self.scale * rec.p;
+        let phase = p.z() + self.turbulence * self.perlin.turbulence(p, &self.fractal);
+        // sharp veins on a wide base
+        let t = (1.0 - (0.5 + 0.5 * Scalar::sin(common::PI * phase))).powi(4);
+        mix(self.base.value(rec), self.vein.value(rec), t)
+    }
+}
+
+// Growth rings around the y axis, warped by fbm
+pub struct WoodTexture {
+    light: SP<dyn Texture>,
+    dark: SP<dyn Texture>,
+    // rings per unit of distance to the axis
+    rings: Scalar,
+    scale: Scalar,
+    turbulence: Scalar,
+    fractal: Fractal,
+    perlin: Perlin,
+}
+
+impl WoodTexture {
+    pub fn new(light: SP<dyn Texture>, dark: SP<dyn Texture>, rings: Scalar, scale: Scalar, turbulence: Scalar, fractal: Fractal) -> WoodTexture {
+        WoodTexture { light, dark, rings, scale, turbulence, fractal, perlin: scene_perlin() }
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        let p = rec.p;
+        let radius = Scalar::sqrt(p.x() * p.x() + p.z() * p.z());
+        let ring = self.rings * radius + self.turbulence * self.perlin.fbm(self.scale * p, &self.fractal);
+        // late wood : a thin dark band at the end of each ring
+        let t = ring.rem_euclid(1.0).powi(3);
+        mix(self.light.value(rec), self.dark.value(rec), t)
+    }
+}
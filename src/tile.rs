@@ -0,0 +1,100 @@
+use clap::ValueEnum;
+
+// Order in which the tiles are handed to the render threads
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum TileOrder {
+    // left to right, top to bottom
+    Scanline,
+    // from the center of the image outwards
+    Spiral,
+    // along a Hilbert curve, consecutive tiles stay close to each other
+    Hilbert,
+}
+
+// Pixels [x0, x1) x [y0, y1) of the image
+#[derive(Copy, Clone)]
+pub struct Tile {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Tile {
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    pub fn nb_pixels(&self) -> u32 {
+        self.width() * (self.y1 - self.y0)
+    }
+
+    // grown by margin pixels on each side, within the image
+    pub fn expand(&self, margin: u32, image_width: u32, image_height: u32) -> Tile {
+        Tile {
+            x0: self.x0.saturating_sub(margin),
+            y0: self.y0.saturating_sub(margin),
+            x1: (self.x1 + margin).min(image_width),
+            y1: (self.y1 + margin).min(image_height),
+        }
+    }
+}
+
+// distance along the Hilbert curve covering a size x size grid, size being a power of two
+fn hilbert_index(size: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut d = 0;
+    let mut s = size / 2;
+    while s > 0 {
+        let rx = ((x & s) > 0) as u32;
+        let ry = ((y & s) > 0) as u32;
+        d += (s as u64) * (s as u64) * ((3 * rx) ^ ry) as u64;
+        // rotate the quadrant
+        if ry == 0 {
+            if rx == 1 {
+                x = size - 1 - x;
+                y = size - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+pub fn make_tiles(width: u32, height: u32, tile_size: u32, order: TileOrder) -> Vec<Tile> {
+    let nb_x = width.div_ceil(tile_size);
+    let nb_y = height.div_ceil(tile_size);
+
+    let mut coords: Vec<(u32, u32)> = (0..nb_y)
+        .flat_map(|ty| (0..nb_x).map(move |tx| (tx, ty)))
+        .collect();
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            // ring by ring around the central tile, each ring swept by angle
+            let cx = (nb_x as f64 - 1.0) / 2.0;
+            let cy = (nb_y as f64 - 1.0) / 2.0;
+            coords.sort_by(|&(ax, ay), &(bx, by)| {
+                let key = |x: u32, y: u32| {
+                    let dx = x as f64 - cx;
+                    let dy = y as f64 - cy;
+                    (dx.abs().max(dy.abs()).round(), f64::atan2(dy, dx))
+                };
+                key(ax, ay).partial_cmp(&key(bx, by)).unwrap()
+            });
+        }
+        TileOrder::Hilbert => {
+            let size = nb_x.max(nb_y).next_power_of_two();
+            coords.sort_by_key(|&(x, y)| hilbert_index(size, x, y));
+        }
+    }
+
+    coords.into_iter()
+        .map(|(tx, ty)| Tile {
+            x0: tx * tile_size,
+            y0: ty * tile_size,
+            x1: ((tx + 1) * tile_size).min(width),
+            y1: ((ty + 1) * tile_size).min(height),
+        })
+        .collect()
+}
@@ -0,0 +1,221 @@
+use crate::aabb::Aabb;
+use crate::bvh::BvhNode;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::material::Material;
+use crate::common::{self, SP};
+use crate::sampler::Sampler;
+use crate::ray::Ray;
+use crate::vec3::{self, cross, dot, Point3, Scalar, Vec3};
+
+// Möller–Trumbore, returns t and the barycentric coordinates of the hit relative to p1 and p2
+fn intersect(ray: &Ray, p0: Point3, p1: Point3, p2: Point3, t_min: Scalar, t_max: Scalar) -> Option<(Scalar, Scalar, Scalar)> {
+    const EPSILON: Scalar = 1e-12;
+    let e1 = p1 - p0;
+    let e2 = p2 - p0;
+    let p = cross(ray.direction(), e2);
+    let det = dot(e1, p);
+    if det.abs() < EPSILON {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let s = ray.origin() - p0;
+    let b1 = dot(s, p) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let q = cross(s, e1);
+    let b2 = dot(ray.direction(), q) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = dot(e2, q) * inv_det;
+    if t <= t_min || t_max <= t {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+fn bounds(p0: Point3, p1: Point3, p2: Point3) -> Aabb {
+    Aabb::surrounding(&Aabb::new(p0, p1), &Aabb::new(p2, p2)).padded()
+}
+
+#[derive(Clone)]
+pub struct Triangle {
+    p0: Point3,
+    p1: Point3,
+    p2: Point3,
+    normal: Vec3,
+    area: Scalar,
+    mat: SP<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Triangle {
+    // counter clockwise vertices seen from the front face
+    pub fn new(p0: Point3, p1: Point3, p2: Point3, mat: SP<dyn Material>) -> Triangle {
+        let n = cross(p1 - p0, p2 - p0);
+        Triangle {
+            p0,
+            p1,
+            p2,
+            normal: vec3::unit_vector(n),
+            area: 0.5 * n.length(),
+            mat,
+            bbox: bounds(p0, p1, p2),
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: Scalar, t_max: Scalar, rec: &mut HitRecord) -> bool {
+        let Some((t, b1, b2)) = intersect(ray, self.p0, self.p1, self.p2, t_min, t_max) else {
+            return false;
+        };
+
+        rec.t = t;
+        rec.p = ray.at(t);
+        rec.set_face_normal(ray, self.normal);
+        rec.mat = Some(self.mat.clone());
+        rec.uv = Vec3::new(b1, b2, 0.0);
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> Scalar {
+        let mut rec = HitRecord::new();
+        if !self.hit(&Ray::new(origin, direction), 0.001, common::INFINITY, &mut rec) {
+            return 0.0;
+        }
+
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (dot(direction, self.normal) / direction.length()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random_direction(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        // uniform on the triangle
+        let (mut b1, mut b2) = sampler.get_2d();
+        if b1 + b2 > 1.0 {
+            b1 = 1.0 - b1;
+            b2 = 1.0 - b2;
+        }
+        let p = self.p0 + b1 * (self.p1 - self.p0) + b2 * (self.p2 - self.p0);
+        p - origin
+    }
+}
+
+// Vertex buffers shared by all the triangles of a mesh
+#[derive(Default)]
+pub struct MeshData {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub texcoords: Vec<Vec3>,
+}
+
+// Indices in the buffers of MeshData
+#[derive(Copy, Clone)]
+pub struct MeshVertex {
+    pub position: usize,
+    pub normal: Option<usize>,
+    pub texcoord: Option<usize>,
+}
+
+pub struct MeshTriangle {
+    data: SP<MeshData>,
+    vertices: [MeshVertex; 3],
+    mat: SP<dyn Material>,
+}
+
+impl MeshTriangle {
+    fn position(&self, i: usize) -> Point3 {
+        self.data.positions[self.vertices[i].position]
+    }
+
+    // interpolated from the vertices when they all have one
+    fn attribute(&self, buffer: &[Vec3], index: impl Fn(&MeshVertex) -> Option<usize>, b1: Scalar, b2: Scalar) -> Option<Vec3> {
+        let a0 = buffer[index(&self.vertices[0])?];
+        let a1 = buffer[index(&self.vertices[1])?];
+        let a2 = buffer[index(&self.vertices[2])?];
+        Some((1.0 - b1 - b2) * a0 + b1 * a1 + b2 * a2)
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: Scalar, t_max: Scalar, rec: &mut HitRecord) -> bool {
+        let (p0, p1, p2) = (self.position(0), self.position(1), self.position(2));
+        let Some((t, b1, b2)) = intersect(ray, p0, p1, p2, t_min, t_max) else {
+            return false;
+        };
+
+        rec.t = t;
+        rec.p = ray.at(t);
+        rec.set_face_normal(ray, vec3::unit_vector(cross(p1 - p0, p2 - p0)));
+        if let Some(n) = self.attribute(&self.data.normals, |v| v.normal, b1, b2) {
+            // smooth shading, on the side given by the geometric normal
+            let n = vec3::unit_vector(n);
+            rec.normal = if rec.front_face { n } else { -n };
+        }
+        rec.mat = Some(self.mat.clone());
+        rec.uv = self.attribute(&self.data.texcoords, |v| v.texcoord, b1, b2)
+            .unwrap_or(Vec3::new(b1, b2, 0.0));
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        bounds(self.position(0), self.position(1), self.position(2))
+    }
+}
+
+// Triangles sharing their vertex buffers, with their own BVH
+pub struct TriangleMesh {
+    bvh: BvhNode,
+    nb_triangles: usize,
+    // distinct materials of the faces
+    materials: Vec<SP<dyn Material>>,
+}
+
+impl TriangleMesh {
+    pub fn new(data: MeshData, faces: Vec<([MeshVertex; 3], SP<dyn Material>)>) -> TriangleMesh {
+        let data = SP::new(data);
+        let nb_triangles = faces.len();
+        let mut triangles = HittableList::new();
+        let mut materials: Vec<SP<dyn Material>> = Vec::new();
+        for (vertices, mat) in faces {
+            if !materials.iter().any(|m| SP::ptr_eq(m, &mat)) {
+                materials.push(mat.clone());
+            }
+            triangles.add(Box::new(MeshTriangle { data: data.clone(), vertices, mat }));
+        }
+        TriangleMesh {
+            bvh: BvhNode::new(triangles),
+            nb_triangles,
+            materials,
+        }
+    }
+
+    pub fn materials(&self) -> &[SP<dyn Material>] {
+        &self.materials
+    }
+
+    pub fn nb_triangles(&self) -> usize {
+        self.nb_triangles
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: Scalar, t_max: Scalar, rec: &mut HitRecord) -> bool {
+        self.bvh.hit(ray, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+}
@@ -3,7 +3,8 @@ use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub};
 
 pub type Scalar = f64;
 
-use crate::{common, random_double_range};
+use crate::common;
+use crate::sampler::Sampler;
 
 #[derive(Copy, Clone, Default)]
 pub struct Vec3 {
@@ -18,22 +19,6 @@ impl Vec3 {
         Vec3::new(x, x, x)
     }
 
-    pub fn random() -> Vec3 {
-        Vec3::new(
-            common::random_double(),
-            common::random_double(),
-            common::random_double(),
-        )
-    }
-
-    pub fn random_range(min: Scalar, max: Scalar) -> Vec3 {
-        Vec3::new(
-            common::random_double_range(min, max),
-            common::random_double_range(min, max),
-            common::random_double_range(min, max),
-        )
-    }
-
     pub fn x(&self) -> Scalar {
         self.e[0]
     }
@@ -141,6 +126,14 @@ impl Div<Scalar> for Vec3 {
     }
 }
 
+impl Div for Vec3 {
+    type Output = Vec3;
+    
+    fn div(self, rhs: Self) -> Self::Output {
+        Vec3::new(self.x() / rhs.x(), self.y() / rhs.y(), self.z() / rhs.z())
+    }
+}
+
 pub fn dot(u: Vec3, v: Vec3) -> Scalar {
     u.x() * v.x() + u.y() * v.y() + u.z() * v.z()
 }
@@ -160,31 +153,34 @@ pub fn fit01(v: Vec3) -> Vec3 {
     0.5 * (v + Vec3::uni(1.0))
 }
 
-pub fn random_in_unit_sphere() -> Vec3 {
-    loop {
-        let p = Vec3::random_range(-1.0, 1.0);
-        if p.length_squared() < 1.0 {
-            return p;
-        }
-    }
+// the samples are mapped instead of rejected, so that each one takes a fixed number of dimensions
+
+pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Vec3 {
+    let r = Scalar::cbrt(sampler.get_1d());
+    r * random_unit_vector(sampler)
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3::new(
-            random_double_range(-1.0, 1.0),
-            random_double_range(-1.0, 1.0),
-            0.0,
-        );
-        if p.length_squared() >= 1.0 {
-            continue;
-        }
-        return p;
+// concentric mapping of the square, which keeps the strata compact
+pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vec3 {
+    let (u, v) = sampler.get_2d();
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::uni(0.0);
     }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, common::PI / 4.0 * (b / a))
+    } else {
+        (b, common::PI / 2.0 - common::PI / 4.0 * (a / b))
+    };
+    Vec3::new(r * Scalar::cos(theta), r * Scalar::sin(theta), 0.0)
 }
 
-pub fn random_unit_vector() -> Vec3 {
-    unit_vector(random_in_unit_sphere())
+pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3 {
+    let (u, v) = sampler.get_2d();
+    let z = 1.0 - 2.0 * u;
+    let r = Scalar::sqrt((1.0 - z * z).max(0.0));
+    let phi = 2.0 * common::PI * v;
+    Vec3::new(r * Scalar::cos(phi), r * Scalar::sin(phi), z)
 }
 
 pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
@@ -197,20 +193,3 @@ pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: Scalar) -> Vec3 {
     let r_out_parallel = -Scalar::sqrt( Scalar::abs(1.0 - r_out_perp.length_squared()))*n;
     r_out_parallel + r_out_perp
 }
-
-pub fn sign(s: Scalar) -> Scalar {
-    if s < 0.0 {
-        -1.0
-    }
-    else {
-        1.0
-    }
-}
-
-pub fn to_spherical(v: Vec3) -> Vec3 {
-    let r = v.length();
-    let theta = Scalar::acos(v.y() / r);
-    let phi = sign(v.z()) * Scalar::acos(v.x() / (Vec3::new(v.x(), v.z(), 0.0).length()));
-
-    Vec3::new(r, theta, phi)
-}